@@ -0,0 +1,58 @@
+//! Resolution throughput on a small set of hot links.
+//!
+//! Run with `cargo bench`. Each case is measured with the resolver's compiled link
+//! cache enabled (the default) and disabled, to show what compiling once saves.
+
+use std::collections::HashMap;
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use golink::{Link, Resolver};
+
+fn links() -> HashMap<String, Link> {
+    HashMap::from([
+        ("docs".to_string(), Link::new("https://docs.example.com/")),
+        (
+            "prs".to_string(),
+            Link::new(
+                "https://github.com/pulls?q=is:open+is:pr+review-requested:{{ if path }}{ path }{{ else }}@me{{ endif }}+archived:false",
+            ),
+        ),
+        (
+            "gh".to_string(),
+            Link::new("https://github.com/{ org }/{ repo }/pull/{ number }")
+                .with_signature("gh/{org}/{repo}/{number}"),
+        ),
+        ("pr".to_string(), Link::new("go/gh/jameslittle230/golink")),
+    ])
+}
+
+fn resolve(c: &mut Criterion) {
+    let links = links();
+    let resolvers = [
+        ("cached", Resolver::default()),
+        (
+            "uncached",
+            Resolver::builder().compiled_link_capacity(0).build(),
+        ),
+    ];
+    let inputs = [
+        ("literal", "/docs/api/v1"),
+        ("template", "/prs/jameslittle230"),
+        ("signature", "/gh/rust-lang/rust/1"),
+        ("alias", "/pr/42"),
+    ];
+
+    let mut group = c.benchmark_group("resolve");
+    for (case, input) in inputs {
+        for (name, resolver) in &resolvers {
+            group.bench_with_input(BenchmarkId::new(case, name), input, |b, input| {
+                b.iter(|| resolver.resolve(black_box(input), &links).unwrap());
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, resolve);
+criterion_main!(benches);
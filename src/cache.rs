@@ -0,0 +1,504 @@
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use crate::{AsyncLinkStore, Candidate, Link, LinkStore, RequestContext, User};
+
+const DEFAULT_CAPACITY: usize = 1024;
+const DEFAULT_HIT_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_MISS_TTL: Duration = Duration::from_secs(10);
+
+/// A store that remembers recent lookups of another store.
+///
+/// Wrap a store backed by a database (or anything else that's slow to query) so that
+/// hot shortlinks are only fetched once per TTL. Links are cached for a minute and
+/// shortlinks that weren't found for ten seconds by default, keeping at most the 1024
+/// most recently used lookups. Failed lookups are never cached.
+///
+/// Call [`invalidate`](Self::invalidate) whenever a link is created, edited or deleted,
+/// so the change takes effect immediately rather than when the cached lookup expires.
+/// Clones share their cache, so the handler that edits links can hold a clone of the
+/// store the resolver reads from.
+///
+/// Lookups are cached by shortlink alone, so every user is served the same cached
+/// link. If your store's
+/// [`lookup_with_context`](LinkStore::lookup_with_context) returns different links to
+/// different users, enable [`per_user`](Self::per_user).
+///
+/// `CachedStore` is a [`LinkStore`] if the wrapped store is, and an
+/// [`AsyncLinkStore`] if the wrapped store is.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use golink::{CachedStore, Link, Resolver};
+///
+/// let database = |shortlink: &str| (shortlink == "docs").then(|| "https://docs.example.com/");
+/// let store = CachedStore::new(database)
+///     .capacity(10_000)
+///     .hit_ttl(Duration::from_secs(300))
+///     .miss_ttl(Duration::from_secs(30));
+///
+/// let resolved = Resolver::default().resolve("/docs", &store)?;
+/// assert_eq!(resolved.link(), Some(&Link::new("https://docs.example.com/")));
+///
+/// // After editing `docs` in the database
+/// store.invalidate("docs");
+/// # Ok::<(), golink::GolinkError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachedStore<S> {
+    store: S,
+    hit_ttl: Duration,
+    miss_ttl: Duration,
+    per_user: bool,
+    cache: Arc<LinkCache<CacheKey>>,
+}
+
+impl<S> CachedStore<S> {
+    /// Wraps `store` with the default cache settings.
+    #[must_use]
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            hit_ttl: DEFAULT_HIT_TTL,
+            miss_ttl: DEFAULT_MISS_TTL,
+            per_user: false,
+            cache: Arc::new(LinkCache::new(DEFAULT_CAPACITY)),
+        }
+    }
+
+    /// Sets how many lookups are kept; when full, the least recently used one is
+    /// evicted. Defaults to 1024; zero disables caching.
+    ///
+    /// This replaces the cache, so clones made earlier no longer share it.
+    #[must_use]
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.cache = Arc::new(LinkCache::new(capacity));
+        self
+    }
+
+    /// Sets how long found links are cached. Defaults to 60 seconds; zero disables
+    /// caching them, and [`Duration::MAX`] keeps them until they're evicted or
+    /// invalidated.
+    #[must_use]
+    pub fn hit_ttl(mut self, ttl: Duration) -> Self {
+        self.hit_ttl = ttl;
+        self
+    }
+
+    /// Sets how long shortlinks that weren't found are cached. Defaults to 10 seconds;
+    /// zero disables caching misses, and [`Duration::MAX`] keeps them until they're
+    /// evicted or invalidated.
+    #[must_use]
+    pub fn miss_ttl(mut self, ttl: Duration) -> Self {
+        self.miss_ttl = ttl;
+        self
+    }
+
+    /// Caches lookups separately for each requesting user, for stores whose
+    /// [`lookup_with_context`](LinkStore::lookup_with_context) depends on who's asking.
+    /// Defaults to `false`.
+    #[must_use]
+    pub fn per_user(mut self, per_user: bool) -> Self {
+        self.per_user = per_user;
+        self
+    }
+
+    /// The wrapped store.
+    #[must_use]
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Forgets the cached lookups of `shortlink`, for every user, so the next lookup
+    /// reads the wrapped store. Call it when the link is created, edited or deleted.
+    ///
+    /// `shortlink` is the key the link is stored under, e.g. `~alice/notes` for a
+    /// personal link.
+    pub fn invalidate(&self, shortlink: &str) {
+        self.cache.remove_where(|key| key.shortlink == shortlink);
+    }
+
+    /// Forgets every cached lookup.
+    pub fn clear(&self) {
+        self.cache.clear();
+    }
+
+    fn key(&self, shortlink: &str, context: &RequestContext) -> CacheKey {
+        CacheKey {
+            shortlink: shortlink.to_string(),
+            user: context.user.clone().filter(|_| self.per_user),
+        }
+    }
+
+    fn ttl(&self, link: Option<&Link>) -> Duration {
+        if link.is_some() {
+            self.hit_ttl
+        } else {
+            self.miss_ttl
+        }
+    }
+}
+
+impl<S: LinkStore> LinkStore for CachedStore<S> {
+    type Error = S::Error;
+
+    fn lookup(&self, shortlink: &str) -> Result<Option<Link>, S::Error> {
+        self.lookup_with_context(shortlink, &RequestContext::default())
+    }
+
+    fn lookup_with_context(
+        &self,
+        shortlink: &str,
+        context: &RequestContext,
+    ) -> Result<Option<Link>, S::Error> {
+        let key = self.key(shortlink, context);
+        if let Some(cached) = self.cache.get(&key) {
+            return Ok(cached);
+        }
+        let link = self.store.lookup_with_context(shortlink, context)?;
+        self.cache
+            .insert(key, link.clone(), self.ttl(link.as_ref()));
+        Ok(link)
+    }
+
+    fn shortlinks(&self) -> Result<Vec<Candidate>, S::Error> {
+        self.store.shortlinks()
+    }
+}
+
+impl<S: AsyncLinkStore> AsyncLinkStore for CachedStore<S> {
+    type Error = S::Error;
+
+    async fn lookup(&self, shortlink: &str) -> Result<Option<Link>, S::Error> {
+        self.lookup_with_context(shortlink, &RequestContext::default())
+            .await
+    }
+
+    async fn lookup_with_context(
+        &self,
+        shortlink: &str,
+        context: &RequestContext,
+    ) -> Result<Option<Link>, S::Error> {
+        let key = self.key(shortlink, context);
+        if let Some(cached) = self.cache.get(&key) {
+            return Ok(cached);
+        }
+        let link = self.store.lookup_with_context(shortlink, context).await?;
+        self.cache
+            .insert(key, link.clone(), self.ttl(link.as_ref()));
+        Ok(link)
+    }
+
+    async fn shortlinks(&self) -> Result<Vec<Candidate>, S::Error> {
+        self.store.shortlinks().await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    shortlink: String,
+    user: Option<User>,
+}
+
+/// Lookup results, including misses, each kept until its TTL runs out or it becomes
+/// the least recently used entry of a full cache.
+#[derive(Debug)]
+pub(crate) struct LinkCache<K: Hash + Eq> {
+    entries: Option<Mutex<LruCache<K, CacheEntry>>>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    /// When the entry expires, or `None` if its TTL reaches past what an `Instant` can
+    /// represent, so it never does.
+    expires_at: Option<Instant>,
+    link: Option<Link>,
+}
+
+impl<K: Hash + Eq> LinkCache<K> {
+    /// Creates a cache of up to `capacity` lookups, which caches nothing if it's zero.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            entries: NonZeroUsize::new(capacity)
+                .map(|capacity| Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    /// Returns the cached lookup for `key`: `Some(None)` for a cached miss, or `None`
+    /// if it isn't cached or has expired.
+    pub(crate) fn get<Q>(&self, key: &Q) -> Option<Option<Link>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut entries = self
+            .entries
+            .as_ref()?
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match entries.get(key) {
+            Some(entry)
+                if entry
+                    .expires_at
+                    .is_none_or(|expires_at| Instant::now() < expires_at) =>
+            {
+                Some(entry.link.clone())
+            }
+            Some(_) => {
+                entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Caches a lookup for `ttl`; a zero TTL caches nothing.
+    pub(crate) fn insert(&self, key: K, link: Option<Link>, ttl: Duration) {
+        let Some(entries) = &self.entries else {
+            return;
+        };
+        if ttl.is_zero() {
+            return;
+        }
+        let expires_at = Instant::now().checked_add(ttl);
+        entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put(key, CacheEntry { expires_at, link });
+    }
+
+    /// Forgets every cached lookup whose key matches `predicate`.
+    pub(crate) fn remove_where(&self, predicate: impl Fn(&K) -> bool)
+    where
+        K: Clone,
+    {
+        let Some(entries) = &self.entries else {
+            return;
+        };
+        let mut entries = entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let matching: Vec<K> = entries
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| predicate(key))
+            .cloned()
+            .collect();
+        for key in matching {
+            entries.pop(&key);
+        }
+    }
+
+    /// Forgets every cached lookup.
+    pub(crate) fn clear(&self) {
+        if let Some(entries) = &self.entries {
+            entries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GolinkError, Resolver};
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+    use std::fmt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    /// Serves `docs` (differently for alice) and counts its lookups; `flaky` fails.
+    #[derive(Debug, Clone, Default)]
+    struct Database {
+        lookups: Arc<AtomicUsize>,
+    }
+
+    #[derive(Debug)]
+    struct Unavailable;
+
+    impl fmt::Display for Unavailable {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "store unavailable")
+        }
+    }
+
+    impl std::error::Error for Unavailable {}
+
+    impl Database {
+        fn lookups(&self) -> usize {
+            self.lookups.load(Ordering::SeqCst)
+        }
+    }
+
+    impl LinkStore for Database {
+        type Error = Unavailable;
+
+        fn lookup(&self, shortlink: &str) -> Result<Option<Link>, Unavailable> {
+            self.lookup_with_context(shortlink, &RequestContext::default())
+        }
+
+        fn lookup_with_context(
+            &self,
+            shortlink: &str,
+            context: &RequestContext,
+        ) -> Result<Option<Link>, Unavailable> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            let is_alice = context
+                .user
+                .as_ref()
+                .is_some_and(|user| user.login == "alice");
+            match shortlink {
+                "docs" if is_alice => Ok(Some(Link::new("https://docs.example.com/alice"))),
+                "docs" => Ok(Some(Link::new("https://docs.example.com/"))),
+                "flaky" => Err(Unavailable),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[test]
+    fn it_caches_hits_and_misses() {
+        let store = CachedStore::new(Database::default());
+        let resolver = Resolver::default();
+
+        for _ in 0..3 {
+            assert!(resolver.resolve("/docs", &store).is_ok());
+            assert!(matches!(
+                resolver.resolve("/missing", &store),
+                Err(GolinkError::NotFound { .. })
+            ));
+        }
+        assert_eq!(store.store().lookups(), 2);
+
+        // Failures are never cached
+        for _ in 0..2 {
+            assert!(matches!(
+                resolver.resolve("/flaky", &store),
+                Err(GolinkError::LookupFailed(_))
+            ));
+        }
+        assert_eq!(store.store().lookups(), 4);
+    }
+
+    #[test]
+    fn it_invalidates_edited_links() {
+        let store = CachedStore::new(Database::default()).per_user(true);
+        let alice = RequestContext::new().with_user(User::new("alice"));
+        store.lookup("docs").unwrap();
+        store.lookup_with_context("docs", &alice).unwrap();
+        store.lookup("missing").unwrap();
+        assert_eq!(store.store().lookups(), 3);
+
+        // A clone shares the cache, and invalidating covers every user
+        store.clone().invalidate("docs");
+        store.lookup("docs").unwrap();
+        store.lookup_with_context("docs", &alice).unwrap();
+        store.lookup("missing").unwrap();
+        assert_eq!(store.store().lookups(), 5);
+
+        store.clear();
+        store.lookup("missing").unwrap();
+        assert_eq!(store.store().lookups(), 6);
+    }
+
+    #[test]
+    fn it_caches_per_user_when_asked() {
+        let alice = RequestContext::new().with_user(User::new("alice"));
+        let bob = RequestContext::new().with_user(User::new("bob"));
+
+        let shared = CachedStore::new(Database::default());
+        shared.lookup_with_context("docs", &bob).unwrap();
+        assert_eq!(
+            shared.lookup_with_context("docs", &alice).unwrap(),
+            Some(Link::new("https://docs.example.com/"))
+        );
+
+        let per_user = CachedStore::new(Database::default()).per_user(true);
+        per_user.lookup_with_context("docs", &bob).unwrap();
+        assert_eq!(
+            per_user.lookup_with_context("docs", &alice).unwrap(),
+            Some(Link::new("https://docs.example.com/alice"))
+        );
+        per_user.lookup_with_context("docs", &alice).unwrap();
+        assert_eq!(per_user.store().lookups(), 2);
+    }
+
+    #[test]
+    fn it_evicts_the_least_recently_used_lookup() {
+        let store = CachedStore::new(Database::default()).capacity(2);
+        store.lookup("a").unwrap();
+        store.lookup("b").unwrap();
+        store.lookup("a").unwrap();
+        store.lookup("c").unwrap();
+        assert_eq!(store.store().lookups(), 3);
+
+        store.lookup("a").unwrap();
+        assert_eq!(store.store().lookups(), 3);
+        store.lookup("b").unwrap();
+        assert_eq!(store.store().lookups(), 4);
+
+        let uncached = CachedStore::new(Database::default()).capacity(0);
+        uncached.lookup("a").unwrap();
+        uncached.lookup("a").unwrap();
+        assert_eq!(uncached.store().lookups(), 2);
+    }
+
+    #[test]
+    fn it_expires_lookups() {
+        let store = CachedStore::new(Database::default())
+            .hit_ttl(Duration::from_millis(20))
+            .miss_ttl(Duration::ZERO);
+        store.lookup("docs").unwrap();
+        store.lookup("docs").unwrap();
+        store.lookup("missing").unwrap();
+        store.lookup("missing").unwrap();
+        assert_eq!(store.store().lookups(), 3);
+
+        thread::sleep(Duration::from_millis(30));
+        store.lookup("docs").unwrap();
+        assert_eq!(store.store().lookups(), 4);
+    }
+
+    #[test]
+    fn it_never_expires_lookups_with_the_longest_ttl() {
+        let store = CachedStore::new(Database::default())
+            .hit_ttl(Duration::MAX)
+            .miss_ttl(Duration::MAX);
+        for _ in 0..2 {
+            store.lookup("docs").unwrap();
+            store.lookup("missing").unwrap();
+        }
+        assert_eq!(store.store().lookups(), 2);
+
+        store.invalidate("docs");
+        store.lookup("docs").unwrap();
+        assert_eq!(store.store().lookups(), 3);
+    }
+
+    #[tokio::test]
+    async fn async_it_caches_lookups() {
+        let lookups = AtomicUsize::new(0);
+        let links = HashMap::from([("docs".to_string(), "https://docs.example.com/")]);
+        let lookup = |shortlink: &str| {
+            lookups.fetch_add(1, Ordering::SeqCst);
+            let link = links.get(shortlink).copied();
+            async move { Ok::<_, Infallible>(link) }
+        };
+        let store = CachedStore::new(lookup);
+
+        for _ in 0..3 {
+            let resolved = Resolver::default().resolve_async("/docs", &store).await;
+            assert!(resolved.is_ok());
+        }
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,178 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use lru::LruCache;
+
+use crate::expand::CompiledUrl;
+use crate::{GolinkError, Link, LinkKind};
+
+/// A link whose long URL has been parsed once, ready to be expanded for any request.
+///
+/// Compiling decides whether the long URL is a template (see [`LinkKind`]), checks and
+/// prepares the template, parses literal URLs and parses the link's
+/// [signature](Link::signature). A [`Resolver`](crate::Resolver) compiles every link it
+/// resolves and keeps the results in a small cache, so popular links are only parsed
+/// once; see [`ResolverBuilder::compiled_link_capacity`](crate::ResolverBuilder::compiled_link_capacity).
+///
+/// Cloning a `CompiledLink` is cheap: the compiled long URL is shared.
+///
+/// # Examples
+///
+/// ```
+/// use golink::{Link, LinkKind, Resolver};
+///
+/// let compiled = Resolver::default().compile(Link::new("https://github.com/{ path }"))?;
+/// assert_eq!(compiled.kind(), LinkKind::Template);
+/// assert_eq!(compiled.link().url, "https://github.com/{ path }");
+/// # Ok::<(), golink::GolinkError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompiledLink {
+    link: Link,
+    url: Arc<CompiledUrl>,
+}
+
+impl CompiledLink {
+    pub(crate) fn new(link: Link, url: Arc<CompiledUrl>) -> Self {
+        Self { link, url }
+    }
+
+    /// The link that was compiled.
+    #[must_use]
+    pub fn link(&self) -> &Link {
+        &self.link
+    }
+
+    /// How the long URL is expanded: [`LinkKind::Template`] if it is rendered, or
+    /// [`LinkKind::Literal`] if it is used as-is. Never [`LinkKind::Auto`].
+    #[must_use]
+    pub fn kind(&self) -> LinkKind {
+        self.url.kind()
+    }
+
+    /// Returns the link that was compiled.
+    #[must_use]
+    pub fn into_link(self) -> Link {
+        self.link
+    }
+
+    pub(crate) fn url(&self) -> &CompiledUrl {
+        &self.url
+    }
+}
+
+/// Compiled long URLs shared by the clones of a resolver.
+///
+/// Entries are keyed by long URL, and each is checked against the link's kind and
+/// signature before reuse. When the cache is full, the least recently used entry is
+/// evicted.
+#[derive(Debug, Default)]
+pub(crate) struct CompiledCache {
+    entries: Option<Mutex<LruCache<String, Arc<CompiledUrl>>>>,
+}
+
+impl CompiledCache {
+    /// Creates a cache of up to `capacity` long URLs, which caches nothing if it's zero.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            entries: NonZeroUsize::new(capacity)
+                .map(|capacity| Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    /// Returns the compiled long URL of `link`, compiling it with `compile` on a miss.
+    pub(crate) fn get_or_compile(
+        &self,
+        link: &Link,
+        compile: impl FnOnce() -> Result<CompiledUrl, GolinkError>,
+    ) -> Result<Arc<CompiledUrl>, GolinkError> {
+        let Some(entries) = &self.entries else {
+            return compile().map(Arc::new);
+        };
+
+        let cached = entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&link.url)
+            .filter(|url| url.matches(&link.url, link.kind, link.signature.as_deref()))
+            .cloned();
+        if let Some(url) = cached {
+            return Ok(url);
+        }
+
+        // Compiled without holding the lock, so other links can be served meanwhile
+        let url = Arc::new(compile()?);
+        entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put(link.url.clone(), Arc::clone(&url));
+        Ok(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TemplateEngine;
+    use pretty_assertions::assert_eq;
+
+    fn compile(cache: &CompiledCache, link: &Link) -> Arc<CompiledUrl> {
+        cache
+            .get_or_compile(link, || {
+                CompiledUrl::compile(
+                    &link.url,
+                    link.kind,
+                    link.signature.as_deref(),
+                    &TemplateEngine::TinyTemplate,
+                )
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn it_reuses_compiled_urls() {
+        let cache = CompiledCache::new(8);
+        let link = Link::new("https://example.com/{ path }");
+        assert!(Arc::ptr_eq(
+            &compile(&cache, &link),
+            &compile(&cache, &link)
+        ));
+    }
+
+    #[test]
+    fn it_recompiles_when_kind_or_signature_change() {
+        let cache = CompiledCache::new(8);
+        let link = Link::new("https://example.com/{ org }");
+        assert_eq!(compile(&cache, &link).kind(), LinkKind::Literal);
+
+        let signed = link.clone().with_signature("gh/{org}");
+        assert_eq!(compile(&cache, &signed).kind(), LinkKind::Template);
+
+        let literal = signed.with_kind(LinkKind::Literal);
+        assert_eq!(compile(&cache, &literal).kind(), LinkKind::Literal);
+    }
+
+    #[test]
+    fn it_evicts_the_least_recently_used_url_when_full() {
+        let cache = CompiledCache::new(2);
+        let first = Link::new("https://a.example.com/");
+        let second = Link::new("https://b.example.com/");
+        let cached = compile(&cache, &first);
+        let evicted = compile(&cache, &second);
+        assert!(Arc::ptr_eq(&cached, &compile(&cache, &first)));
+
+        compile(&cache, &Link::new("https://c.example.com/"));
+        assert!(Arc::ptr_eq(&cached, &compile(&cache, &first)));
+        assert!(!Arc::ptr_eq(&evicted, &compile(&cache, &second)));
+    }
+
+    #[test]
+    fn it_compiles_every_time_without_capacity() {
+        let cache = CompiledCache::new(0);
+        let link = Link::new("https://example.com/");
+        assert!(!Arc::ptr_eq(
+            &compile(&cache, &link),
+            &compile(&cache, &link)
+        ));
+    }
+}
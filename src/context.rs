@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::normalize::names_match;
+
+/// What's known about the request being resolved, beyond its URL.
+///
+/// Pass a context to [`Resolver::resolve_with_context`](crate::Resolver::resolve_with_context)
+/// (or its async counterpart) so that templates can refer to the requesting user, like
+/// `https://profiles.example.com/{ user.login }` for `go/me`, and so that stores can
+/// return user-aware links through
+/// [`LinkStore::lookup_with_context`](crate::LinkStore::lookup_with_context).
+///
+/// # Examples
+///
+/// ```
+/// use golink::{GolinkResolution, RequestContext, Resolver, User};
+///
+/// let context = RequestContext::new().with_user(User::new("jameslittle230"));
+/// let lookup = |_: &str| Some("https://github.com/{ user.login }");
+/// let resolution = Resolver::default().resolve_with_context("/me", &context, &lookup)?;
+/// assert!(matches!(
+///     resolution,
+///     GolinkResolution::RedirectRequest { url, .. } if url == "https://github.com/jameslittle230"
+/// ));
+/// # Ok::<(), golink::GolinkError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct RequestContext {
+    /// The user making the request, if they're known.
+    #[serde(default)]
+    pub user: Option<User>,
+}
+
+impl RequestContext {
+    /// Creates a context for an anonymous request.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the user making the request.
+    #[must_use]
+    pub fn with_user(mut self, user: User) -> Self {
+        self.user = Some(user);
+        self
+    }
+}
+
+/// The identity of a requesting user, as established by your authentication layer.
+///
+/// Templates see it as `user`: `{ user.login }`, `{ user.display_name }`,
+/// `{ user.groups }` and `{ user.attributes.name }`.
+///
+/// # Examples
+///
+/// ```
+/// use golink::User;
+///
+/// let user = User::new("jameslittle230@example.com")
+///     .with_display_name("James Little")
+///     .with_groups(["eng"])
+///     .with_attribute("team", "search");
+///
+/// assert_eq!(user.attributes["team"], "search");
+/// assert!(user.in_group("Eng"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct User {
+    /// The user's login, like a username or email address.
+    pub login: String,
+
+    /// The user's name for display.
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// The groups the user belongs to.
+    #[serde(default)]
+    pub groups: Vec<String>,
+
+    /// Any other attributes your deployment knows about the user.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl User {
+    /// Creates a user with the given login and no other attributes.
+    #[must_use]
+    pub fn new(login: impl Into<String>) -> Self {
+        Self {
+            login: login.into(),
+            display_name: None,
+            groups: Vec::new(),
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the user's display name.
+    #[must_use]
+    pub fn with_display_name(mut self, display_name: impl Into<String>) -> Self {
+        self.display_name = Some(display_name.into());
+        self
+    }
+
+    /// Sets the groups the user belongs to.
+    #[must_use]
+    pub fn with_groups<I, T>(mut self, groups: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.groups = groups.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Adds an attribute.
+    #[must_use]
+    pub fn with_attribute(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(name.into(), value.into());
+        self
+    }
+
+    /// Returns whether the user belongs to `group`. Group names are compared ignoring
+    /// case, as in [`Access`](crate::Access).
+    #[must_use]
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| names_match(g, group))
+    }
+}
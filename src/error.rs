@@ -0,0 +1,261 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+
+use thiserror::Error;
+use url::ParseError;
+
+/// Errors that can occur during shortlink resolution.
+///
+/// These errors are designed to map naturally to HTTP status codes:
+/// - `InvalidInput` → HTTP 400 Bad Request
+/// - `NotFound` → HTTP 404 Not Found
+/// - `Forbidden` → HTTP 403 Forbidden
+/// - `MissingParameters` → HTTP 400 Bad Request
+/// - `TemplateError` → HTTP 500 Internal Server Error
+/// - `AliasCycle` → HTTP 500 Internal Server Error
+/// - `BrokenAlias` → HTTP 404 Not Found
+/// - `UnsafeTarget` → HTTP 500 Internal Server Error
+/// - `LookupFailed` → HTTP 503 Service Unavailable
+///
+/// # Example: Mapping to HTTP Status Codes
+///
+/// ```
+/// use golink::{resolve, GolinkError};
+///
+/// fn lookup(key: &str) -> Option<String> {
+///     // Your lookup implementation
+/// #   None
+/// }
+///
+/// fn handle_request(path: &str) -> (u16, String) {
+///     match resolve(path, lookup) {
+///         Ok(resolution) => {
+///             // Handle successful resolution
+/// #           (200, "OK".to_string())
+///         }
+///         Err(GolinkError::InvalidInput) => {
+///             (400, format!("Invalid shortlink {path}"))
+///         }
+///         Err(GolinkError::NotFound { shortlink, suggestions }) => {
+///             let hint = suggestions.first().map(|s| format!("; did you mean go/{s}?"));
+///             (404, format!("Shortlink '{shortlink}' not found{}", hint.unwrap_or_default()))
+///         }
+///         Err(GolinkError::Forbidden(shortlink)) => {
+///             (403, format!("You don't have access to '{shortlink}'"))
+///         }
+///         Err(GolinkError::MissingParameters { signature, missing }) => {
+///             (400, format!("Missing {}; usage: go/{signature}", missing.join(", ")))
+///         }
+///         Err(GolinkError::TemplateError(msg)) => {
+///             // Log this error - it indicates a data integrity problem
+///             eprintln!("Template error: {msg}");
+///             (500, "Internal Server Error".to_string())
+///         }
+///         Err(GolinkError::AliasCycle(chain)) => {
+///             // Log this error - an alias points back at itself
+///             eprintln!("Alias cycle: {}", chain.join(" -> "));
+///             (500, "Internal Server Error".to_string())
+///         }
+///         Err(GolinkError::BrokenAlias(shortlink)) => {
+///             (404, format!("Shortlink '{shortlink}' points at a link that doesn't exist"))
+///         }
+///         Err(GolinkError::UnsafeTarget { shortlink, reason }) => {
+///             // Log this error - the link may have been tampered with
+///             eprintln!("Shortlink '{shortlink}' is unsafe: {reason}");
+///             (500, "Internal Server Error".to_string())
+///         }
+///         Err(GolinkError::LookupFailed(error)) => {
+///             // Log this error - your store (e.g. a database) is unavailable
+///             eprintln!("Lookup failed: {}", error.get_ref());
+///             (503, "Service Unavailable".to_string())
+///         }
+///     }
+/// }
+/// ```
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum GolinkError {
+    /// The input shortlink is invalid or malformed.
+    ///
+    /// This covers cases like:
+    /// - Strings that don't make sense as a URL path (e.g. `a:b`)
+    /// - Empty input, or empty input after normalization
+    /// - A shortlink whose percent-escapes don't decode to valid UTF-8 (e.g. `%FF`)
+    ///
+    /// **Recommended HTTP status: 400 Bad Request**
+    #[error("Invalid input")]
+    InvalidInput,
+
+    /// The shortlink was not found in the lookup function.
+    ///
+    /// The lookup function returned `None` (or `Ok(None)`) for the given shortlink.
+    /// `shortlink` is the normalized shortlink that was not found, and `suggestions`
+    /// lists similar stored shortlinks, best first, if the resolver was built with
+    /// [suggestions](crate::ResolverBuilder::suggestions) enabled.
+    ///
+    /// **Recommended HTTP status: 404 Not Found**
+    #[error("Shortlink '{shortlink}' not found")]
+    NotFound {
+        /// The normalized shortlink that was not found.
+        shortlink: String,
+        /// Stored shortlinks the request may have meant, like `standup` for `standpu`.
+        suggestions: Vec<String>,
+    },
+
+    /// The link's [access policy](crate::Access) doesn't admit the requesting user, or
+    /// the request named another user's or group's [layer](crate::LinkLayer), as in
+    /// `go/~alice/notes`.
+    ///
+    /// The contained `String` is the normalized shortlink that was requested. The long
+    /// URL is deliberately left out, so the error is safe to show to the user.
+    ///
+    /// **Recommended HTTP status: 403 Forbidden**
+    #[error("Access to shortlink '{0}' is forbidden")]
+    Forbidden(String),
+
+    /// The request didn't supply every required parameter of the link's
+    /// [signature](crate::Link::signature).
+    ///
+    /// `signature` is the link's declared signature (e.g. `gh/{org}/{repo}`), suitable
+    /// for showing as usage help, and `missing` lists the required parameters that had
+    /// no path segment to bind to, in signature order.
+    ///
+    /// **Recommended HTTP status: 400 Bad Request**
+    #[error("Missing parameters {} for '{signature}'", missing.join(", "))]
+    MissingParameters {
+        /// The link's declared signature.
+        signature: String,
+        /// The names of the required parameters that weren't supplied.
+        missing: Vec<String>,
+    },
+
+    /// The long URL contains invalid template syntax.
+    ///
+    /// This indicates a configuration or data integrity problem - the stored long URL
+    /// has malformed template syntax. This is not the user's fault.
+    ///
+    /// **Recommended HTTP status: 500 Internal Server Error**
+    #[error("Template error: {0}")]
+    TemplateError(String),
+
+    /// The link is an alias whose chain of targets loops back on itself, or is longer
+    /// than the resolver's [maximum alias depth](crate::ResolverBuilder::max_alias_depth).
+    ///
+    /// The contained `Vec` lists the normalized shortlinks in the chain, in the order
+    /// they were followed, ending with the one that closed the loop or went too deep.
+    /// Like `TemplateError`, this indicates a data integrity problem.
+    ///
+    /// **Recommended HTTP status: 500 Internal Server Error**
+    #[error("Alias cycle: {}", .0.join(" -> "))]
+    AliasCycle(Vec<String>),
+
+    /// The link is an alias whose target isn't stored, e.g. because the target was
+    /// deleted or renamed.
+    ///
+    /// The contained `String` is the normalized shortlink that was requested. The alias
+    /// target is deliberately left out, as for `Forbidden`. Unlike `NotFound`, the
+    /// requested shortlink exists, so the resolver's
+    /// [fallback policy](crate::ResolverBuilder::fallback) doesn't apply.
+    ///
+    /// **Recommended HTTP status: 404 Not Found**
+    #[error("Shortlink '{0}' is an alias for a link that doesn't exist")]
+    BrokenAlias(String),
+
+    /// The expanded URL breaks the resolver's [safety policy](crate::SafetyPolicy), for
+    /// example by using a `javascript:` scheme or containing a line break.
+    ///
+    /// `shortlink` is the normalized shortlink that was requested, and `reason` says
+    /// which rule the target broke. The target itself is left out, so it can't be echoed
+    /// back to the user. Like `TemplateError`, this indicates a data integrity problem -
+    /// or a link edit that deserves a closer look.
+    ///
+    /// **Recommended HTTP status: 500 Internal Server Error**
+    #[error("Shortlink '{shortlink}' expands to an unsafe target: {reason}")]
+    UnsafeTarget {
+        /// The normalized shortlink that was requested.
+        shortlink: String,
+        /// Why the expanded URL was rejected.
+        reason: String,
+    },
+
+    /// The lookup function or store failed before it could say whether the shortlink exists.
+    ///
+    /// The contained [`LookupError`] carries the store's own error (e.g. a database
+    /// connection failure), available through [`std::error::Error::source`],
+    /// [`LookupError::get_ref`] or [`LookupError::downcast_ref`]. This error's own
+    /// message doesn't repeat it, so reporters that walk the source chain print it
+    /// once. This is not the user's fault, and the link may well exist, so don't report
+    /// it as not found.
+    ///
+    /// **Recommended HTTP status: 503 Service Unavailable**
+    #[error(transparent)]
+    LookupFailed(LookupError),
+}
+
+/// An error returned by a lookup function or store, carried by
+/// [`GolinkError::LookupFailed`].
+///
+/// The store's error is its [`source`](StdError::source), and is left out of its
+/// message. It's reference-counted so that `GolinkError` stays cheap to clone, and two
+/// `LookupError`s are equal only if they share the same underlying error: clones are
+/// equal, but two lookups that failed with identical errors are not.
+#[derive(Debug, Clone)]
+pub struct LookupError(Arc<dyn StdError + Send + Sync + 'static>);
+
+impl LookupError {
+    /// Wraps a store's error.
+    pub fn new(error: impl StdError + Send + Sync + 'static) -> Self {
+        Self(Arc::new(error))
+    }
+
+    /// Returns the store's error.
+    #[must_use]
+    pub fn get_ref(&self) -> &(dyn StdError + Send + Sync + 'static) {
+        &*self.0
+    }
+
+    /// Returns the store's error, if it is of type `E`.
+    #[must_use]
+    pub fn downcast_ref<E: StdError + 'static>(&self) -> Option<&E> {
+        self.0.downcast_ref()
+    }
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Lookup failed")
+    }
+}
+
+impl StdError for LookupError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+impl PartialEq for LookupError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for LookupError {}
+
+impl From<ParseError> for GolinkError {
+    fn from(_: ParseError) -> Self {
+        GolinkError::InvalidInput
+    }
+}
+
+impl From<tinytemplate::error::Error> for GolinkError {
+    fn from(tt_error: tinytemplate::error::Error) -> Self {
+        GolinkError::TemplateError(tt_error.to_string())
+    }
+}
+
+#[cfg(feature = "minijinja")]
+impl From<minijinja::Error> for GolinkError {
+    fn from(jinja_error: minijinja::Error) -> Self {
+        GolinkError::TemplateError(jinja_error.to_string())
+    }
+}
@@ -0,0 +1,1407 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use lru::LruCache;
+
+use itertools::Itertools;
+use percent_encoding::{
+    AsciiSet, NON_ALPHANUMERIC, percent_decode_str, percent_encode, utf8_percent_encode,
+};
+use self_cell::self_cell;
+use serde::Serialize;
+use tinytemplate::{TinyTemplate, format_unescaped};
+use url::{Url, form_urlencoded};
+
+use crate::{CompiledTemplate, Expander, GolinkError, LinkKind, RequestContext, User};
+
+/// The built-in template engines for long URLs.
+///
+/// Each engine is an [`Expander`]; use
+/// [`ResolverBuilder::expander`](crate::ResolverBuilder::expander) to plug in your own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TemplateEngine {
+    /// Render long URLs with [TinyTemplate](https://docs.rs/tinytemplate) syntax,
+    /// e.g. `{{ if path }}{ path }{{ else }}@me{{ endif }}`.
+    ///
+    /// Values are percent-escaped for the part of the URL they're placed in: path
+    /// escaping before the first `?` or `#`, query escaping after it. The
+    /// `path_escape`, `query_escape` and `unescaped` formatters (`{ path | unescaped }`)
+    /// override this per placeholder.
+    ///
+    /// Besides `path`, templates can use `shortlink`, `raw_path`, `segments` (e.g.
+    /// `{ segments.0 }`), `segment_count`, `query`, `user` (e.g. `{ user.login }`) and
+    /// the named parameters of the link's [signature](crate::Link::signature).
+    ///
+    /// TinyTemplate's parsed templates can't be shared between threads, so each thread
+    /// parses a template the first time it renders it, keeping the 1024 it used most
+    /// recently.
+    #[default]
+    TinyTemplate,
+
+    /// Render long URLs with the Go `text/template` syntax of
+    /// [Tailscale's golink](https://github.com/tailscale/golink), e.g.
+    /// `{{if .Path}}search?q={{QueryEscape .Path}}{{end}}`, so links exported from a
+    /// Tailscale instance resolve the same way.
+    ///
+    /// Templates can use `.Path`, `.User` and `.Now`, and the `PathEscape`,
+    /// `QueryEscape`, `TrimPrefix`, `TrimSuffix`, `ToLower`, `ToUpper` and `Match`
+    /// functions. Values are inserted as-is, as Tailscale does. A long URL is a
+    /// template if it contains `{{`.
+    ///
+    /// Requires the `go-template` feature, which is enabled by default.
+    #[cfg(feature = "go-template")]
+    GoTemplate,
+
+    /// Render long URLs with [MiniJinja](https://docs.rs/minijinja), a Jinja2-style
+    /// syntax, e.g. `{% if path %}search?q={{ path | query_escape }}{% endif %}`.
+    ///
+    /// Templates see the same values as TinyTemplate ones, and can use the
+    /// `path_escape` and `query_escape` filters. Values are inserted as-is unless
+    /// filtered. A long URL is a template if it contains `{{` or `{%`.
+    ///
+    /// Requires the `minijinja` feature.
+    #[cfg(feature = "minijinja")]
+    Jinja,
+
+    /// Treat every long URL as a literal; template syntax is never interpreted.
+    Disabled,
+}
+
+/// How the remaining path segments of a request are combined with a long URL
+/// that doesn't use them in a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathAppend {
+    /// Append the remaining path segments to the long URL's path, so `go/docs/api`
+    /// resolves to `https://docs.example.com/api`.
+    #[default]
+    Append,
+
+    /// Drop the remaining path segments and redirect to the long URL as-is.
+    Discard,
+}
+
+/// Characters left unescaped in query-string values: RFC 3986 unreserved characters.
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Characters left unescaped in path segments: unreserved characters plus the
+/// sub-delimiters that are meaningful but harmless inside a segment.
+pub(crate) const PATH_SEGMENT: &AsciiSet = &QUERY_VALUE
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b',')
+    .remove(b':')
+    .remove(b';')
+    .remove(b'=')
+    .remove(b'@');
+
+/// How the query string of a request is combined with the long URL's own query.
+///
+/// Whichever is chosen, templates can read the request's parameters as
+/// `{ query.name }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryPassthrough {
+    /// Drop the request's query string: `go/search?q=rust` redirects to the long URL as-is.
+    #[default]
+    Discard,
+
+    /// Add the request's parameters to the long URL, except those it already sets.
+    KeepTarget,
+
+    /// Add the request's parameters to the long URL, replacing any it already sets.
+    PreferRequest,
+
+    /// Add all of the request's parameters after the long URL's own, even where
+    /// names repeat.
+    Append,
+}
+
+/// Names that templates already use, which a link's signature can't redefine.
+const RESERVED_NAMES: &[&str] = &[
+    "shortlink",
+    "path",
+    "raw_path",
+    "segments",
+    "segment_count",
+    "query",
+    "user",
+];
+
+/// The values a long URL template is rendered with, for a single request.
+///
+/// Serializes to the variables templates see: `shortlink`, `path`, `raw_path`,
+/// `segments`, `segment_count`, `query`, `user` and the named parameters of the link's
+/// [signature](crate::Link::signature).
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpandEnvironment {
+    /// The normalized shortlink that was requested, for `{ shortlink }`.
+    shortlink: String,
+
+    /// The remaining path segments of the request, percent-decoded and joined with `/`.
+    pub(crate) path: String,
+
+    /// The remaining path segments of the request as they were requested, still
+    /// percent-encoded, joined with `/`.
+    raw_path: String,
+
+    /// The non-empty remaining path segments, percent-decoded, for `{ segments.0 }`.
+    segments: Vec<String>,
+
+    /// The number of entries in `segments`.
+    segment_count: usize,
+
+    /// The decoded query parameters of the request, for `{ query.name }`. Where a name
+    /// repeats, the first value wins.
+    query: BTreeMap<String, String>,
+
+    /// The requesting user, for `{ user.login }`.
+    user: Option<User>,
+
+    /// Named parameters declared by the link's signature, bound to `segments` in order.
+    #[serde(flatten)]
+    params: BTreeMap<String, String>,
+}
+
+impl ExpandEnvironment {
+    /// Builds the environment from the remaining (still percent-encoded) path segments
+    /// of a request, binding them to the named parameters of `signature`, if any.
+    pub(crate) fn new(
+        remainder: &[String],
+        signature: Option<&Signature>,
+    ) -> Result<Self, GolinkError> {
+        let decoded = remainder
+            .iter()
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+            .collect_vec();
+        let segments = decoded
+            .iter()
+            .filter(|segment| !segment.is_empty())
+            .cloned()
+            .collect_vec();
+
+        let mut params = BTreeMap::new();
+        if let Some(signature) = signature {
+            let mut missing = Vec::new();
+            for (index, (name, required)) in signature.params.iter().enumerate() {
+                match segments.get(index) {
+                    Some(value) => params.insert(name.clone(), value.clone()),
+                    None if *required => {
+                        missing.push(name.clone());
+                        continue;
+                    }
+                    None => params.insert(name.clone(), String::new()),
+                };
+            }
+
+            if !missing.is_empty() {
+                return Err(GolinkError::MissingParameters {
+                    signature: signature.source.clone(),
+                    missing,
+                });
+            }
+        }
+
+        Ok(Self {
+            shortlink: String::new(),
+            path: decoded.join("/"),
+            raw_path: remainder.join("/"),
+            segment_count: segments.len(),
+            segments,
+            query: BTreeMap::new(),
+            user: None,
+            params,
+        })
+    }
+
+    /// Exposes the request's raw query string to templates.
+    pub(crate) fn with_query(mut self, query: Option<&str>) -> Self {
+        for (name, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            self.query
+                .entry(name.into_owned())
+                .or_insert_with(|| value.into_owned());
+        }
+        self
+    }
+
+    /// Exposes the requested shortlink to templates.
+    pub(crate) fn with_shortlink(mut self, shortlink: &str) -> Self {
+        shortlink.clone_into(&mut self.shortlink);
+        self
+    }
+
+    /// Exposes the requesting user to templates.
+    pub(crate) fn with_context(mut self, context: &RequestContext) -> Self {
+        self.user.clone_from(&context.user);
+        self
+    }
+
+    /// The normalized shortlink that was requested.
+    #[must_use]
+    pub fn shortlink(&self) -> &str {
+        &self.shortlink
+    }
+
+    /// The remaining path segments of the request, percent-decoded and joined with `/`.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The remaining path segments of the request, still percent-encoded, joined with
+    /// `/`. Unlike [`path`](Self::path), this tells an escaped `%2F` apart from a `/`.
+    #[must_use]
+    pub fn raw_path(&self) -> &str {
+        &self.raw_path
+    }
+
+    /// The non-empty remaining path segments, percent-decoded.
+    #[must_use]
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    /// The decoded query parameters of the request. Where a name repeats, the first
+    /// value wins.
+    #[must_use]
+    pub fn query(&self) -> &BTreeMap<String, String> {
+        &self.query
+    }
+
+    /// The requesting user, if the request's [`RequestContext`] named one.
+    #[must_use]
+    pub fn user(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+
+    /// The named parameters of the link's signature, bound to the request's segments.
+    #[must_use]
+    pub fn params(&self) -> &BTreeMap<String, String> {
+        &self.params
+    }
+}
+
+/// A link signature like `gh/{org}/{repo}/{number?}`, parsed into its parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Signature {
+    /// The signature as written.
+    source: String,
+
+    /// The parameter names, each paired with whether it is required.
+    params: Vec<(String, bool)>,
+}
+
+impl Signature {
+    pub(crate) fn parse(signature: &str) -> Result<Self, GolinkError> {
+        let invalid = |reason: &str| {
+            GolinkError::TemplateError(format!("Invalid signature '{signature}': {reason}"))
+        };
+
+        let mut params: Vec<(String, bool)> = Vec::new();
+        for segment in signature.split('/').filter(|segment| !segment.is_empty()) {
+            let Some(name) = segment
+                .strip_prefix('{')
+                .and_then(|segment| segment.strip_suffix('}'))
+            else {
+                if params.is_empty() {
+                    continue;
+                }
+                return Err(invalid("parameters must come after the shortlink"));
+            };
+
+            let (name, required) = match name.trim().strip_suffix('?') {
+                Some(name) => (name.trim(), false),
+                None => (name.trim(), true),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(invalid(
+                    "parameter names may only contain letters, digits and '_'",
+                ));
+            }
+            if RESERVED_NAMES.contains(&name) || params.iter().any(|(other, _)| other == name) {
+                return Err(invalid(&format!("parameter '{name}' is already defined")));
+            }
+            if required && params.last().is_some_and(|(_, required)| !required) {
+                return Err(invalid("required parameters can't follow optional ones"));
+            }
+
+            params.push((name.to_string(), required));
+        }
+
+        Ok(Self {
+            source: signature.to_string(),
+            params,
+        })
+    }
+}
+
+/// A piece of template syntax found in a long URL.
+enum Syntax<'a> {
+    /// A `{{ ... }}` block, holding its trimmed contents.
+    Block(&'a str),
+    /// A `{ ... }` value placeholder, holding its trimmed contents.
+    Value(&'a str),
+    /// A `\`-escaped character.
+    Escape,
+}
+
+/// The keywords that can open a TinyTemplate block.
+const BLOCK_KEYWORDS: &[&str] = &[
+    "if", "else", "endif", "for", "endfor", "with", "endwith", "call",
+];
+
+/// The formatters available to templates.
+const FORMATTERS: &[&str] = &["path_escape", "query_escape", "unescaped"];
+
+/// Finds everything in `template` that TinyTemplate would treat as syntax.
+fn scan(template: &str) -> Vec<Syntax<'_>> {
+    let mut syntax = Vec::new();
+    let mut rest = template;
+
+    while let Some(index) = rest.find(['\\', '{']) {
+        let tail = &rest[index..];
+        let end = if tail.starts_with('\\') {
+            syntax.push(Syntax::Escape);
+            tail.char_indices()
+                .nth(2)
+                .map_or(tail.len(), |(end, _)| end)
+        } else if let Some(block) = tail.strip_prefix("{{") {
+            let end = block.find("}}").unwrap_or(block.len());
+            syntax.push(Syntax::Block(block[..end].trim()));
+            (end + 4).min(tail.len())
+        } else {
+            let end = tail.find('}').unwrap_or(tail.len());
+            syntax.push(Syntax::Value(tail[1..end].trim()));
+            (end + 1).min(tail.len())
+        };
+        rest = &tail[end..];
+    }
+
+    syntax
+}
+
+/// Splits a value placeholder into its path and formatter, if it is shaped like one.
+fn value_parts(value: &str) -> Option<(&str, Option<&str>)> {
+    let (path, formatter) = match value.split_once('|') {
+        Some((path, formatter)) => (path.trim(), Some(formatter.trim())),
+        None => (value, None),
+    };
+    let is_path = !path.is_empty()
+        && path
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '[' | ']' | '@'));
+    let is_formatter = formatter.is_none_or(|formatter| {
+        !formatter.is_empty() && formatter.chars().all(|c| c.is_alphanumeric() || c == '_')
+    });
+    (is_path && is_formatter).then_some((path, formatter))
+}
+
+/// Returns the variable a placeholder path starts from, e.g. `segments` for `segments[0]`.
+fn path_root(path: &str) -> &str {
+    path.split(['.', '[']).next().unwrap_or(path)
+}
+
+/// Decides whether a long URL of the given kind is rendered as a template.
+///
+/// An [`Auto`](LinkKind::Auto) long URL is a template if it contains a block, an
+/// escape, or a placeholder naming a variable that `defines` recognizes.
+fn is_template(input: &str, kind: LinkKind, defines: impl Fn(&str) -> bool) -> bool {
+    match kind {
+        LinkKind::Template => true,
+        LinkKind::Literal => false,
+        LinkKind::Auto => scan(input).into_iter().any(|syntax| match syntax {
+            Syntax::Block(block) => block
+                .split_whitespace()
+                .next()
+                .is_some_and(|keyword| BLOCK_KEYWORDS.contains(&keyword)),
+            Syntax::Value(value) => {
+                value_parts(value).is_some_and(|(path, _)| defines(path_root(path)))
+            }
+            Syntax::Escape => true,
+        }),
+    }
+}
+
+/// Rewrites `name[0]` index syntax in a template expression to TinyTemplate's `name.0`.
+fn dotted_indices(expression: &str) -> String {
+    expression.replace('[', ".").replace(']', "")
+}
+
+/// Escapes `value` for use in a URL path, keeping `/` as the segment separator.
+pub(crate) fn path_escape(value: &str) -> String {
+    value
+        .split('/')
+        .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT))
+        .join("/")
+}
+
+/// Escapes an as-requested path for use in a URL path, keeping `/` as the segment
+/// separator and escaped characters like `%2F` escaped.
+fn raw_path_escape(raw: &str) -> String {
+    raw.split('/')
+        .map(|segment| {
+            let bytes: Vec<u8> = percent_decode_str(segment).collect();
+            percent_encode(&bytes, PATH_SEGMENT).to_string()
+        })
+        .join("/")
+}
+
+/// Escapes `value` for use as a query-string (or fragment) value.
+pub(crate) fn query_escape(value: &str) -> String {
+    utf8_percent_encode(value, QUERY_VALUE).to_string()
+}
+
+/// Returns the query parameter names a template refers to as `query.name`.
+fn referenced_query_names(template: &str) -> impl Iterator<Item = &str> {
+    template
+        .match_indices("query.")
+        .filter_map(|(index, prefix)| {
+            let preceded_by_name = template[..index]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.');
+            let rest = &template[index + prefix.len()..];
+            let end = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '}' | '|' | '.'))
+                .unwrap_or(rest.len());
+            (!preceded_by_name && end > 0).then(|| &rest[..end])
+        })
+}
+
+/// Prepares a TinyTemplate long URL for rendering.
+///
+/// Every value placeholder that doesn't name a formatter gets the escaping formatter
+/// for where it sits: `path_escape` before the first `?` or `#`, and `query_escape`
+/// after it. Index syntax like `segments[0]` is rewritten to `segments.0`.
+fn prepare_template(template: &str) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut formatter = "path_escape";
+    let mut rest = template;
+
+    while let Some(index) = rest.find(['\\', '{', '?', '#']) {
+        let (literal, tail) = rest.split_at(index);
+        output.push_str(literal);
+
+        let block_end = if tail.starts_with('\\') {
+            // Escaped character: copy it through untouched
+            tail.char_indices()
+                .nth(2)
+                .map_or(tail.len(), |(end, _)| end)
+        } else if tail.starts_with("{{") {
+            let end = tail.find("}}").map_or(tail.len(), |end| end + 2);
+            output.push_str(&dotted_indices(&tail[..end]));
+            rest = &tail[end..];
+            continue;
+        } else if tail.starts_with('{') {
+            match tail.find('}') {
+                Some(end) if !tail[..end].contains('|') => {
+                    let expression = dotted_indices(tail[1..end].trim());
+                    output.push_str(&format!("{{ {expression} | {formatter} }}"));
+                    rest = &tail[end + 1..];
+                    continue;
+                }
+                Some(end) => {
+                    output.push_str(&dotted_indices(&tail[..=end]));
+                    rest = &tail[end + 1..];
+                    continue;
+                }
+                None => tail.len(),
+            }
+        } else {
+            formatter = "query_escape";
+            1
+        };
+
+        output.push_str(&tail[..block_end]);
+        rest = &tail[block_end..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+impl Expander for TemplateEngine {
+    fn is_template(&self, url: &str, kind: LinkKind, variables: &[&str]) -> bool {
+        match self {
+            Self::TinyTemplate => is_template(url, kind, |name| variables.contains(&name)),
+            #[cfg(feature = "go-template")]
+            Self::GoTemplate => {
+                kind == LinkKind::Template || (kind == LinkKind::Auto && url.contains("{{"))
+            }
+            #[cfg(feature = "minijinja")]
+            Self::Jinja => crate::jinja::is_template(url, kind),
+            Self::Disabled => false,
+        }
+    }
+
+    fn compile(
+        &self,
+        url: &str,
+        variables: &[&str],
+    ) -> Result<Box<dyn CompiledTemplate>, GolinkError> {
+        match self {
+            Self::TinyTemplate => Ok(Box::new(TinyTemplateUrl::compile(url, variables)?)),
+            #[cfg(feature = "go-template")]
+            Self::GoTemplate => Ok(Box::new(crate::gotemplate::Template::parse(url)?)),
+            #[cfg(feature = "minijinja")]
+            Self::Jinja => Ok(Box::new(crate::jinja::JinjaUrl::compile(url, variables)?)),
+            Self::Disabled => Err(GolinkError::TemplateError(
+                "Templates are disabled".to_string(),
+            )),
+        }
+    }
+}
+
+/// A long URL compiled for [`TemplateEngine::TinyTemplate`].
+#[derive(Debug)]
+struct TinyTemplateUrl {
+    /// Identifies the template in each thread's [`PARSED_TEMPLATES`].
+    id: u64,
+
+    /// The template as written.
+    input: String,
+
+    /// The template with escaping formatters filled in, as TinyTemplate parses it.
+    source: String,
+
+    /// The query parameters the template refers to as `query.name`.
+    query_names: Vec<String>,
+
+    /// The variables the template can use.
+    variables: Vec<String>,
+}
+
+impl TinyTemplateUrl {
+    fn compile(input: &str, variables: &[&str]) -> Result<Self, GolinkError> {
+        let source = prepare_template(input);
+        let query_names = referenced_query_names(&source)
+            .map(str::to_string)
+            .unique()
+            .collect();
+        let url = Self {
+            id: NEXT_TEMPLATE_ID.fetch_add(1, Ordering::Relaxed),
+            input: input.to_string(),
+            source,
+            query_names,
+            variables: variables.iter().map(|name| (*name).to_string()).collect(),
+        };
+        // Parsing checks the syntax, and leaves the template ready for this thread
+        url.with_parsed(|_| Ok(()))?;
+        Ok(url)
+    }
+
+    /// Calls `f` with this thread's parsed copy of the template, parsing it first if
+    /// the thread hasn't yet.
+    fn with_parsed<T>(
+        &self,
+        f: impl FnOnce(&TinyTemplate<'_>) -> Result<T, GolinkError>,
+    ) -> Result<T, GolinkError> {
+        PARSED_TEMPLATES.with_borrow_mut(|parsed| {
+            let template =
+                parsed.try_get_or_insert(self.id, || ParsedTemplate::parse(&self.source))?;
+            f(template.borrow_dependent())
+        })
+    }
+}
+
+impl CompiledTemplate for TinyTemplateUrl {
+    fn render(&self, environment: &ExpandEnvironment) -> Result<String, GolinkError> {
+        // Parameters the request didn't supply render as empty rather than failing, so
+        // templates can test for them with `{{ if query.name }}`
+        let mut environment = Cow::Borrowed(environment);
+        for name in &self.query_names {
+            if !environment.query.contains_key(name) {
+                environment
+                    .to_mut()
+                    .query
+                    .insert(name.clone(), String::new());
+            }
+        }
+
+        self.with_parsed(|template| Ok(template.render(TEMPLATE_NAME, &*environment)?))
+    }
+
+    /// Checks for unknown variables, unknown formatters and malformed placeholders.
+    fn validate(&self) -> Result<(), GolinkError> {
+        let syntax = scan(&self.input);
+        let bound = syntax
+            .iter()
+            .filter_map(|syntax| match syntax {
+                Syntax::Block(block) => {
+                    let words = block.split_whitespace().collect_vec();
+                    match words.as_slice() {
+                        ["for", name, "in", ..] => Some(*name),
+                        [.., "as", name] => Some(*name),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect_vec();
+
+        for syntax in &syntax {
+            let Syntax::Value(value) = syntax else {
+                continue;
+            };
+            let Some((path, formatter)) = value_parts(value) else {
+                return Err(GolinkError::TemplateError(format!(
+                    "Invalid placeholder '{{{value}}}'"
+                )));
+            };
+
+            let root = path_root(path);
+            if !self.variables.iter().any(|name| name == root)
+                && !bound.contains(&root)
+                && !root.starts_with('@')
+            {
+                return Err(GolinkError::TemplateError(format!(
+                    "Unknown template variable '{root}'"
+                )));
+            }
+            if let Some(formatter) = formatter.filter(|formatter| !FORMATTERS.contains(formatter)) {
+                return Err(GolinkError::TemplateError(format!(
+                    "Unknown formatter '{formatter}'"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The name of the only template in a [`ParsedTemplate`]'s registry.
+const TEMPLATE_NAME: &str = "url_input";
+
+/// How many parsed TinyTemplate templates each thread keeps.
+const PARSED_TEMPLATE_CAPACITY: NonZeroUsize = NonZeroUsize::new(1024).unwrap();
+
+/// The next [`TinyTemplateUrl::id`].
+static NEXT_TEMPLATE_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The TinyTemplate templates parsed on this thread, by [`TinyTemplateUrl::id`].
+    ///
+    /// A TinyTemplate registry can't be shared between threads, so each thread parses
+    /// a template the first time it renders it and reuses it after that.
+    static PARSED_TEMPLATES: RefCell<LruCache<u64, ParsedTemplate>> =
+        RefCell::new(LruCache::new(PARSED_TEMPLATE_CAPACITY));
+}
+
+type Registry<'a> = TinyTemplate<'a>;
+
+self_cell!(
+    /// A TinyTemplate registry holding one parsed long URL template, along with the
+    /// source it borrows from.
+    struct ParsedTemplate {
+        owner: String,
+
+        #[covariant]
+        dependent: Registry,
+    }
+);
+
+impl ParsedTemplate {
+    fn parse(source: &str) -> Result<Self, GolinkError> {
+        Self::try_new(source.to_string(), |source| {
+            let mut registry = TinyTemplate::new();
+            registry.set_default_formatter(&format_unescaped);
+            registry.add_formatter("path_escape", |value, output| {
+                let mut text = String::new();
+                format_unescaped(value, &mut text)?;
+                output.push_str(&path_escape(&text));
+                Ok(())
+            });
+            registry.add_formatter("query_escape", |value, output| {
+                let mut text = String::new();
+                format_unescaped(value, &mut text)?;
+                output.push_str(&query_escape(&text));
+                Ok(())
+            });
+            registry.add_template(TEMPLATE_NAME, source)?;
+            Ok(registry)
+        })
+    }
+}
+
+/// A long URL parsed once, ready to be expanded for any request.
+///
+/// Compiling decides whether the long URL is a template, compiles the template with
+/// the resolver's [`Expander`], parses literal URLs and parses the link's signature, so
+/// none of that is repeated per request.
+#[derive(Debug)]
+pub(crate) struct CompiledUrl {
+    /// The long URL as stored.
+    input: String,
+
+    /// The link kind the URL was compiled for, before `Auto` was decided.
+    requested_kind: LinkKind,
+
+    signature: Option<Signature>,
+
+    form: Form,
+}
+
+/// How a compiled long URL is expanded.
+#[derive(Debug)]
+enum Form {
+    /// Rendered by the expander's compiled template.
+    Template(Box<dyn CompiledTemplate>),
+
+    /// Used as-is, with any remaining path appended. Holds the parsed URL, unless the
+    /// long value isn't a URL.
+    Literal(Option<Url>),
+}
+
+impl CompiledUrl {
+    pub(crate) fn compile(
+        input: &str,
+        kind: LinkKind,
+        signature: Option<&str>,
+        expander: &dyn Expander,
+    ) -> Result<Self, GolinkError> {
+        let signature = signature.map(Signature::parse).transpose()?;
+
+        let variables = RESERVED_NAMES
+            .iter()
+            .copied()
+            .chain(
+                signature
+                    .iter()
+                    .flat_map(|signature| signature.params.iter().map(|(name, _)| name.as_str())),
+            )
+            .collect_vec();
+        let form = if kind != LinkKind::Literal && expander.is_template(input, kind, &variables) {
+            Form::Template(expander.compile(input, &variables)?)
+        } else {
+            Form::Literal(Url::parse(input).ok())
+        };
+
+        Ok(Self {
+            input: input.to_string(),
+            requested_kind: kind,
+            signature,
+            form,
+        })
+    }
+
+    /// Returns whether this was compiled from the given long URL, kind and signature.
+    pub(crate) fn matches(&self, input: &str, kind: LinkKind, signature: Option<&str>) -> bool {
+        self.input == input
+            && self.requested_kind == kind
+            && self
+                .signature
+                .as_ref()
+                .map(|signature| signature.source.as_str())
+                == signature
+    }
+
+    /// How the long URL is expanded: [`LinkKind::Template`] or [`LinkKind::Literal`].
+    pub(crate) fn kind(&self) -> LinkKind {
+        match self.form {
+            Form::Template(_) => LinkKind::Template,
+            Form::Literal(_) => LinkKind::Literal,
+        }
+    }
+
+    /// Builds the template environment for a request's remaining path segments.
+    pub(crate) fn environment(
+        &self,
+        remainder: &[String],
+    ) -> Result<ExpandEnvironment, GolinkError> {
+        ExpandEnvironment::new(remainder, self.signature.as_ref())
+    }
+
+    pub(crate) fn expand(
+        &self,
+        environment: &ExpandEnvironment,
+        path_append: PathAppend,
+    ) -> Result<String, GolinkError> {
+        let url = match &self.form {
+            Form::Template(template) => return template.render(environment),
+            Form::Literal(url) => url,
+        };
+
+        // Literal long URLs get the incoming remainder path appended onto their path, as
+        // requested so that escaped slashes and question marks stay escaped
+        let append = path_append == PathAppend::Append && !environment.raw_path.is_empty();
+        match url {
+            Some(url) if append => {
+                let mut url = url.clone();
+                let base_path = url.path().trim_end_matches('/');
+                url.set_path(&format!(
+                    "{base_path}/{}",
+                    raw_path_escape(&environment.raw_path)
+                ));
+                Ok(url.into())
+            }
+            Some(url) => Ok(url.to_string()),
+            None if append => Ok(format!(
+                "{}/{}",
+                self.input,
+                raw_path_escape(&environment.raw_path)
+            )),
+            None => Ok(self.input.clone()),
+        }
+    }
+
+    /// Checks a template for mistakes that would otherwise only surface when a request
+    /// reaches them: unknown variables, unknown formatters and malformed placeholders.
+    pub(crate) fn validate(&self) -> Result<(), GolinkError> {
+        match &self.form {
+            Form::Template(template) => template.validate(),
+            Form::Literal(_) => Ok(()),
+        }
+    }
+}
+
+/// Carries a request's query string and fragment over to an expanded long URL.
+///
+/// Works on the raw text of both query strings, so the long URL's own parameters are
+/// never re-encoded. A request fragment is only used when the long URL has none.
+pub(crate) fn pass_through(
+    url: String,
+    query: Option<&str>,
+    fragment: Option<&str>,
+    passthrough: QueryPassthrough,
+) -> String {
+    let query = query.filter(|_| passthrough != QueryPassthrough::Discard);
+    if query.is_none_or(str::is_empty) && fragment.is_none_or(str::is_empty) {
+        return url;
+    }
+
+    let (url, target_fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url.as_str(), None),
+    };
+    let (base, target_query) = url.split_once('?').unwrap_or((url, ""));
+
+    let target_pairs = named_pairs(target_query);
+    let request_pairs = named_pairs(query.unwrap_or_default());
+    let has_name = |pairs: &[(String, &str)], name: &str| pairs.iter().any(|(n, _)| n == name);
+
+    let merged = match passthrough {
+        QueryPassthrough::Discard | QueryPassthrough::Append => {
+            target_pairs.iter().chain(&request_pairs).collect_vec()
+        }
+        QueryPassthrough::KeepTarget => target_pairs
+            .iter()
+            .chain(
+                request_pairs
+                    .iter()
+                    .filter(|(name, _)| !has_name(&target_pairs, name)),
+            )
+            .collect_vec(),
+        QueryPassthrough::PreferRequest => target_pairs
+            .iter()
+            .filter(|(name, _)| !has_name(&request_pairs, name))
+            .chain(&request_pairs)
+            .collect_vec(),
+    };
+
+    let mut output = base.to_string();
+    if !merged.is_empty() {
+        output.push('?');
+        output.push_str(&merged.iter().map(|(_, pair)| *pair).join("&"));
+    } else if url.contains('?') {
+        output.push('?');
+    }
+    if let Some(fragment) = target_fragment.or(fragment.filter(|f| !f.is_empty())) {
+        output.push('#');
+        output.push_str(fragment);
+    }
+    output
+}
+
+/// Splits a raw query string into its `name=value` pairs, each keyed by its decoded name.
+fn named_pairs(query: &str) -> Vec<(String, &str)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let name = form_urlencoded::parse(pair.as_bytes())
+                .next()
+                .map(|(name, _)| name.into_owned())
+                .unwrap_or_default();
+            (name, pair)
+        })
+        .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn expand(
+        input: &str,
+        kind: LinkKind,
+        environment: &ExpandEnvironment,
+        engine: TemplateEngine,
+        path_append: PathAppend,
+    ) -> Result<String, GolinkError> {
+        CompiledUrl::compile(input, kind, None, &engine)?.expand(environment, path_append)
+    }
+
+    fn validate(
+        input: &str,
+        kind: LinkKind,
+        signature: Option<&str>,
+        engine: TemplateEngine,
+    ) -> Result<LinkKind, GolinkError> {
+        let url = CompiledUrl::compile(input, kind, signature, &engine)?;
+        url.validate()?;
+        Ok(url.kind())
+    }
+
+    fn render(template: &str, path: &str) -> String {
+        let environment = ExpandEnvironment::new(&[path.to_string()], None).unwrap();
+        expand(
+            template,
+            LinkKind::Auto,
+            &environment,
+            TemplateEngine::TinyTemplate,
+            PathAppend::Append,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn it_parses_tinytemplate_templates_once_per_thread() {
+        let url = TinyTemplateUrl::compile("https://example.com/{ path }", &["path"]).unwrap();
+        let parsed =
+            || PARSED_TEMPLATES.with_borrow(|parsed| parsed.peek(&url.id).map(std::ptr::from_ref));
+        let render = |path: &str| {
+            let environment = ExpandEnvironment::new(&[path.to_string()], None).unwrap();
+            url.render(&environment).unwrap()
+        };
+
+        // Compiling parsed it for this thread, and rendering reuses that
+        let compiled = parsed();
+        assert!(compiled.is_some());
+        for path in ["a", "b", "c"] {
+            assert_eq!(render(path), format!("https://example.com/{path}"));
+            assert_eq!(parsed(), compiled);
+        }
+
+        // Other threads parse their own copy when they first render it
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                assert_eq!(parsed(), None);
+                assert_eq!(render("d"), "https://example.com/d");
+                assert!(parsed().is_some());
+            });
+        });
+    }
+
+    #[test]
+    fn it_escapes_placeholders_in_the_path() {
+        let template = "https://example.com/{ path }";
+        assert_eq!(render(template, "a&b"), "https://example.com/a&b");
+        assert_eq!(render(template, "it's"), "https://example.com/it%27s");
+        assert_eq!(render(template, "a#b"), "https://example.com/a%23b");
+        assert_eq!(render(template, "a?b"), "https://example.com/a%3Fb");
+        assert_eq!(render(template, "a b"), "https://example.com/a%20b");
+        assert_eq!(
+            render(template, "ünï/cödé"),
+            "https://example.com/%C3%BCn%C3%AF/c%C3%B6d%C3%A9"
+        );
+    }
+
+    #[test]
+    fn it_escapes_placeholders_in_the_query() {
+        let template = "https://google.com/search?q={ path }";
+        assert_eq!(render(template, "a&b"), "https://google.com/search?q=a%26b");
+        assert_eq!(
+            render(template, "it's"),
+            "https://google.com/search?q=it%27s"
+        );
+        assert_eq!(render(template, "a#b"), "https://google.com/search?q=a%23b");
+        assert_eq!(render(template, "a?b"), "https://google.com/search?q=a%3Fb");
+        assert_eq!(
+            render(template, "a b/c"),
+            "https://google.com/search?q=a%20b%2Fc"
+        );
+        assert_eq!(render(template, "ü"), "https://google.com/search?q=%C3%BC");
+    }
+
+    #[test]
+    fn it_escapes_placeholders_in_the_fragment() {
+        assert_eq!(
+            render("https://example.com/docs#{ path }", "a b#c"),
+            "https://example.com/docs#a%20b%23c"
+        );
+    }
+
+    #[test]
+    fn it_uses_explicit_formatters() {
+        assert_eq!(
+            render("https://example.com/{ path | unescaped }", "a b&c"),
+            "https://example.com/a b&c"
+        );
+        assert_eq!(
+            render("https://example.com/{ path | query_escape }", "a/b"),
+            "https://example.com/a%2Fb"
+        );
+        assert_eq!(
+            render("https://example.com/?q={ path | path_escape }", "a/b c"),
+            "https://example.com/?q=a/b%20c"
+        );
+    }
+
+    #[test]
+    fn it_leaves_blocks_and_escapes_alone() {
+        assert_eq!(
+            render(
+                "https://example.com/search?q={{ if path }}{ path }{{ else }}a&b{{ endif }}",
+                ""
+            ),
+            "https://example.com/search?q=a&b"
+        );
+        assert_eq!(
+            render("https://example.com/\\{{ path }", "a b"),
+            "https://example.com/{a%20b"
+        );
+    }
+
+    #[test]
+    fn it_appends_the_remainder_as_requested() {
+        let environment = ExpandEnvironment::new(
+            &["a%2Fb".to_string(), "c%3Fd".to_string(), "e f".to_string()],
+            None,
+        )
+        .unwrap();
+        let append = |input| {
+            expand(
+                input,
+                LinkKind::Literal,
+                &environment,
+                TemplateEngine::TinyTemplate,
+                PathAppend::Append,
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            append("https://example.com/docs/"),
+            "https://example.com/docs/a%2Fb/c%3Fd/e%20f"
+        );
+        assert_eq!(append("docs"), "docs/a%2Fb/c%3Fd/e%20f");
+    }
+
+    #[test]
+    fn it_decodes_the_remainder_before_escaping() {
+        let environment =
+            ExpandEnvironment::new(&["a%20b".to_string(), "c&d".to_string()], None).unwrap();
+        assert_eq!(environment.path, "a b/c&d");
+        assert_eq!(environment.raw_path(), "a%20b/c&d");
+        assert_eq!(
+            expand(
+                "https://example.com/search?q={ path }",
+                LinkKind::Auto,
+                &environment,
+                TemplateEngine::TinyTemplate,
+                PathAppend::Append
+            ),
+            Ok("https://example.com/search?q=a%20b%2Fc%26d".to_string())
+        );
+        assert_eq!(
+            expand(
+                "https://example.com/",
+                LinkKind::Auto,
+                &environment,
+                TemplateEngine::TinyTemplate,
+                PathAppend::Append
+            ),
+            Ok("https://example.com/a%20b/c&d".to_string())
+        );
+    }
+
+    fn render_segments(
+        template: &str,
+        segments: &[&str],
+        signature: Option<&str>,
+    ) -> Result<String, GolinkError> {
+        let segments = segments.iter().map(ToString::to_string).collect_vec();
+        let url = CompiledUrl::compile(
+            template,
+            LinkKind::Auto,
+            signature,
+            &TemplateEngine::TinyTemplate,
+        )?;
+        url.expand(&url.environment(&segments)?, PathAppend::Append)
+    }
+
+    #[test]
+    fn it_exposes_individual_segments() {
+        assert_eq!(
+            render_segments(
+                "https://github.com/{ segments.0 }/{ segments[1] }?n={ segment_count }",
+                &["rust-lang", "rust", ""],
+                None
+            ),
+            Ok("https://github.com/rust-lang/rust?n=2".to_string())
+        );
+        assert_eq!(
+            render_segments(
+                "https://example.com/{{ for segment in segments }}-{ segment }{{ endfor }}",
+                &["a", "b c"],
+                None
+            ),
+            Ok("https://example.com/-a-b%20c".to_string())
+        );
+    }
+
+    #[test]
+    fn it_binds_signature_parameters() {
+        assert_eq!(
+            render_segments(
+                "https://github.com/{ org }/{ repo }/pull/{ number }",
+                &["rust-lang", "rust", "1"],
+                Some("gh/{org}/{repo}/{number}")
+            ),
+            Ok("https://github.com/rust-lang/rust/pull/1".to_string())
+        );
+        assert_eq!(
+            render_segments(
+                "https://github.com/{ org }/{ repo }{{ if tab }}/{ tab }{{ endif }}",
+                &["rust-lang", "rust"],
+                Some("gh/{org}/{repo}/{tab?}")
+            ),
+            Ok("https://github.com/rust-lang/rust".to_string())
+        );
+    }
+
+    #[test]
+    fn it_reports_missing_signature_parameters() {
+        assert_eq!(
+            render_segments(
+                "https://github.com/{ org }/{ repo }",
+                &["rust-lang"],
+                Some("gh/{org}/{repo}/{number}")
+            ),
+            Err(GolinkError::MissingParameters {
+                signature: "gh/{org}/{repo}/{number}".to_string(),
+                missing: vec!["repo".to_string(), "number".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_signatures() {
+        for signature in [
+            "gh/{org}/pulls/{number}",
+            "gh/{org?}/{repo}",
+            "gh/{org}/{org}",
+            "gh/{path}",
+            "gh/{a-b}",
+        ] {
+            assert!(
+                matches!(
+                    render_segments("https://example.com/", &["a", "b"], Some(signature)),
+                    Err(GolinkError::TemplateError(_))
+                ),
+                "{signature}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_exposes_query_parameters() {
+        let environment = ExpandEnvironment::new(&[], None)
+            .unwrap()
+            .with_query(Some("q=rust+lang&q=go&page=2"));
+        assert_eq!(
+            expand(
+                "https://example.com/search?q={ query.q }&p={ query.page }",
+                LinkKind::Auto,
+                &environment,
+                TemplateEngine::TinyTemplate,
+                PathAppend::Append
+            ),
+            Ok("https://example.com/search?q=rust%20lang&p=2".to_string())
+        );
+        assert_eq!(
+            expand(
+                "https://example.com/{{ if query.lang }}{ query.lang }{{ else }}en{{ endif }}",
+                LinkKind::Auto,
+                &environment,
+                TemplateEngine::TinyTemplate,
+                PathAppend::Append
+            ),
+            Ok("https://example.com/en".to_string())
+        );
+    }
+
+    #[test]
+    fn it_merges_query_strings() {
+        let target = "https://example.com/s?q=a:b&x=1#top";
+        let request = Some("q=c&y=2");
+        assert_eq!(
+            pass_through(target.into(), request, None, QueryPassthrough::Discard),
+            target
+        );
+        assert_eq!(
+            pass_through(target.into(), request, None, QueryPassthrough::KeepTarget),
+            "https://example.com/s?q=a:b&x=1&y=2#top"
+        );
+        assert_eq!(
+            pass_through(
+                target.into(),
+                request,
+                None,
+                QueryPassthrough::PreferRequest
+            ),
+            "https://example.com/s?x=1&q=c&y=2#top"
+        );
+        assert_eq!(
+            pass_through(target.into(), request, None, QueryPassthrough::Append),
+            "https://example.com/s?q=a:b&x=1&q=c&y=2#top"
+        );
+        assert_eq!(
+            pass_through(
+                "https://example.com/".into(),
+                request,
+                None,
+                QueryPassthrough::KeepTarget
+            ),
+            "https://example.com/?q=c&y=2"
+        );
+    }
+
+    #[test]
+    fn it_carries_fragments_to_targets_without_one() {
+        assert_eq!(
+            pass_through(
+                "https://example.com/?a=1".into(),
+                None,
+                Some("install"),
+                QueryPassthrough::Discard
+            ),
+            "https://example.com/?a=1#install"
+        );
+        assert_eq!(
+            pass_through(
+                "https://example.com/#top".into(),
+                None,
+                Some("install"),
+                QueryPassthrough::Discard
+            ),
+            "https://example.com/#top"
+        );
+    }
+
+    fn expand_kind(template: &str, kind: LinkKind, path: &str) -> Result<String, GolinkError> {
+        let environment = ExpandEnvironment::new(&[path.to_string()], None).unwrap();
+        expand(
+            template,
+            kind,
+            &environment,
+            TemplateEngine::TinyTemplate,
+            PathAppend::Append,
+        )
+    }
+
+    #[test]
+    fn it_treats_unknown_braces_as_literal() {
+        assert_eq!(
+            expand_kind(
+                r#"https://grafana.example.com/d/abc?filter={"a":1}"#,
+                LinkKind::Auto,
+                "x"
+            ),
+            Ok("https://grafana.example.com/d/abc/x?filter={%22a%22:1}".to_string())
+        );
+        assert_eq!(
+            expand_kind("https://example.com/${ var }", LinkKind::Auto, ""),
+            Ok("https://example.com/$%7B%20var%20%7D".to_string())
+        );
+    }
+
+    #[test]
+    fn it_follows_the_link_kind() {
+        assert_eq!(
+            expand_kind("https://example.com/{ path }", LinkKind::Literal, "a"),
+            Ok("https://example.com/%7B%20path%20%7D/a".to_string())
+        );
+        assert_eq!(
+            expand_kind("https://example.com/", LinkKind::Template, "a"),
+            Ok("https://example.com/".to_string())
+        );
+        assert!(matches!(
+            expand_kind(r#"https://example.com/?f={"a":1}"#, LinkKind::Template, ""),
+            Err(GolinkError::TemplateError(_))
+        ));
+        assert_eq!(
+            expand_kind(
+                r#"https://example.com/?f=\{"a":"{ path }"}"#,
+                LinkKind::Auto,
+                "b"
+            ),
+            Ok(r#"https://example.com/?f={"a":"b"}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn it_validates_templates() {
+        let validate = |input: &str, kind, signature| {
+            validate(input, kind, signature, TemplateEngine::TinyTemplate)
+        };
+        assert_eq!(
+            validate(r#"https://example.com/?f={"a":1}"#, LinkKind::Auto, None),
+            Ok(LinkKind::Literal)
+        );
+        assert_eq!(
+            validate(
+                "https://github.com/{ org }",
+                LinkKind::Auto,
+                Some("gh/{org}")
+            ),
+            Ok(LinkKind::Template)
+        );
+        assert_eq!(
+            validate(
+                "https://example.com/{{ for s in segments }}{ s }/{ @index }{{ endfor }}",
+                LinkKind::Auto,
+                None
+            ),
+            Ok(LinkKind::Template)
+        );
+        for (input, message) in [
+            (
+                "https://github.com/{ org }",
+                "Unknown template variable 'org'",
+            ),
+            (
+                "https://example.com/{ path | upper }",
+                "Unknown formatter 'upper'",
+            ),
+            (
+                "https://example.com/{ \"a\" }",
+                "Invalid placeholder '{\"a\"}'",
+            ),
+        ] {
+            assert_eq!(
+                validate(input, LinkKind::Template, None),
+                Err(GolinkError::TemplateError(message.to_string()))
+            );
+        }
+        assert!(matches!(
+            validate("https://example.com/{{ if path }}", LinkKind::Auto, None),
+            Err(GolinkError::TemplateError(_))
+        ));
+        assert_eq!(
+            self::validate(
+                "https://example.com/{ path }",
+                LinkKind::Auto,
+                None,
+                TemplateEngine::Disabled
+            ),
+            Ok(LinkKind::Literal)
+        );
+    }
+}
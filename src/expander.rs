@@ -0,0 +1,101 @@
+use std::fmt::Debug;
+
+use crate::{ExpandEnvironment, GolinkError, LinkKind};
+
+/// A template language for long URLs.
+///
+/// The resolver asks its expander whether each long URL is a template and, if so,
+/// compiles it once into a [`CompiledTemplate`] that is rendered for every request.
+/// Long URLs that aren't templates are used as-is, with any remaining path appended.
+///
+/// [`TemplateEngine`](crate::TemplateEngine) implements this trait for the built-in
+/// engines. Implement it yourself to resolve links written for another link shortener;
+/// install it with [`ResolverBuilder::expander`](crate::ResolverBuilder::expander).
+///
+/// # Examples
+///
+/// ```
+/// use golink::{
+///     CompiledTemplate, ExpandEnvironment, Expander, GolinkError, GolinkResolution, LinkKind,
+///     Resolver,
+/// };
+///
+/// /// Replaces `$1` with the remaining path.
+/// #[derive(Debug)]
+/// struct Dollar;
+///
+/// #[derive(Debug)]
+/// struct DollarTemplate(String);
+///
+/// impl Expander for Dollar {
+///     fn is_template(&self, url: &str, kind: LinkKind, _variables: &[&str]) -> bool {
+///         kind == LinkKind::Template || (kind == LinkKind::Auto && url.contains("$1"))
+///     }
+///
+///     fn compile(
+///         &self,
+///         url: &str,
+///         _variables: &[&str],
+///     ) -> Result<Box<dyn CompiledTemplate>, GolinkError> {
+///         Ok(Box::new(DollarTemplate(url.to_string())))
+///     }
+/// }
+///
+/// impl CompiledTemplate for DollarTemplate {
+///     fn render(&self, environment: &ExpandEnvironment) -> Result<String, GolinkError> {
+///         Ok(self.0.replace("$1", environment.path()))
+///     }
+/// }
+///
+/// let resolver = Resolver::builder().expander(Dollar).build();
+/// let lookup = |_: &str| Some("https://bugs.example.com/show?id=$1");
+/// let resolution = resolver.resolve("/bug/123", &lookup)?;
+/// let GolinkResolution::RedirectRequest { url, .. } = resolution else {
+///     unreachable!()
+/// };
+/// assert_eq!(url, "https://bugs.example.com/show?id=123");
+/// # Ok::<(), GolinkError>(())
+/// ```
+pub trait Expander: Debug + Send + Sync {
+    /// Returns whether a long URL is a template that should be compiled and rendered.
+    ///
+    /// `kind` is the link's [`LinkKind`]: [`LinkKind::Template`] when the link asks to
+    /// be rendered, or [`LinkKind::Auto`] when the expander should decide from the URL.
+    /// Links marked [`LinkKind::Literal`] are never passed to the expander.
+    /// `variables` are the names the template can use: `shortlink`, `path`, `raw_path`,
+    /// `segments`, `segment_count`, `query`, `user` and the parameters of the link's
+    /// [signature](crate::Link::signature).
+    fn is_template(&self, url: &str, kind: LinkKind, variables: &[&str]) -> bool;
+
+    /// Compiles a long URL that [`is_template`](Self::is_template) accepted.
+    ///
+    /// # Errors
+    ///
+    /// - `TemplateError`: The template has invalid syntax
+    fn compile(
+        &self,
+        url: &str,
+        variables: &[&str],
+    ) -> Result<Box<dyn CompiledTemplate>, GolinkError>;
+}
+
+/// A long URL template compiled by an [`Expander`].
+pub trait CompiledTemplate: Debug + Send + Sync {
+    /// Renders the template for a request.
+    ///
+    /// # Errors
+    ///
+    /// - `TemplateError`: The template couldn't be rendered for this request
+    fn render(&self, environment: &ExpandEnvironment) -> Result<String, GolinkError>;
+
+    /// Checks the template for mistakes that would otherwise only surface when a
+    /// request reaches them, like unknown variables. Used by
+    /// [`validate_link()`](crate::validate_link); accepts everything by default.
+    ///
+    /// # Errors
+    ///
+    /// - `TemplateError`: The template refers to something that doesn't exist
+    fn validate(&self) -> Result<(), GolinkError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+/// What the resolver does with a request for a shortlink that isn't stored.
+///
+/// By default it fails with [`GolinkError::NotFound`](crate::GolinkError::NotFound).
+/// The other policies redirect instead, resolving to
+/// [`GolinkResolution::Fallback`](crate::GolinkResolution::Fallback) so that misses
+/// can still be told apart from real hits. Metadata requests for unknown shortlinks
+/// always fail with `NotFound`.
+///
+/// Search and create URLs are treated like a link's long URL: the resolver's
+/// [`TemplateEngine`](crate::TemplateEngine) decides whether they're templates, and
+/// renders them if so. Besides the usual variables, `{ shortlink }` is the normalized
+/// shortlink that wasn't found, and `{ path }` the rest of the request's path. A URL
+/// that isn't a template, such as any URL under
+/// [`TemplateEngine::Disabled`](crate::TemplateEngine::Disabled), has the whole
+/// request path appended to it like a literal link, shortlink included.
+///
+/// # Examples
+///
+/// ```
+/// use golink::{FallbackPolicy, GolinkResolution, Resolver};
+///
+/// let resolver = Resolver::builder()
+///     .fallback(FallbackPolicy::Search(
+///         "https://search.example.com/?q={ shortlink }".to_string(),
+///     ))
+///     .build();
+///
+/// let lookup = |_: &str| None::<String>;
+/// let resolution = resolver.resolve("/Stand-Up", &lookup)?;
+/// assert_eq!(
+///     resolution,
+///     GolinkResolution::Fallback {
+///         url: "https://search.example.com/?q=standup".to_string(),
+///         shortlink: "standup".to_string(),
+///     }
+/// );
+/// # Ok::<(), golink::GolinkError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum FallbackPolicy {
+    /// Fail with [`GolinkError::NotFound`](crate::GolinkError::NotFound).
+    #[default]
+    NotFound,
+
+    /// Redirect to a search page, e.g. `https://search.example.com/?q={ shortlink }`.
+    Search(String),
+
+    /// Redirect to a page for creating the missing link, e.g.
+    /// `https://go.example.com/.create?shortlink={ shortlink }`.
+    Create(String),
+
+    /// Redirect to the same path on another go link service, e.g.
+    /// `https://go.other.example.com/`, keeping the request's path and query string as
+    /// they were requested.
+    Upstream(String),
+}
+
+/// Builds the URL of a request forwarded to an upstream go link service.
+pub(crate) fn upstream_url(base: &str, segments: &[String], query: Option<&str>) -> String {
+    let mut url = format!("{}/{}", base.trim_end_matches('/'), segments.join("/"));
+    if let Some(query) = query.filter(|query| !query.is_empty()) {
+        url.push('?');
+        url.push_str(query);
+    }
+    url
+}
@@ -0,0 +1,278 @@
+use std::collections::HashSet;
+use std::error::Error as StdError;
+
+use crate::{AsyncLinkStore, Candidate, Link, LinkStore, RequestContext};
+
+/// A store that looks links up locally first, then on an upstream store.
+///
+/// Use it to federate go link services, e.g. a team instance that falls back on the
+/// company-wide one: links stored locally win, and anything missing is looked up
+/// upstream, typically with an `HttpStore` (see the `http` feature). Both stores see
+/// the same normalized shortlink and request context. If the local store fails, the
+/// lookup fails without asking upstream, so an outage never silently swaps a local
+/// link for an upstream one.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use golink::{Federated, Resolver};
+///
+/// let team = HashMap::from([("standup".to_string(), "https://meet.example.com/team")]);
+/// let company = HashMap::from([
+///     ("standup".to_string(), "https://meet.example.com/all-hands"),
+///     ("wiki".to_string(), "https://wiki.example.com/"),
+/// ]);
+/// let store = Federated::new(team, company);
+///
+/// let resolved = Resolver::default().resolve("/wiki", &store)?;
+/// assert_eq!(resolved.link().unwrap().url, "https://wiki.example.com/");
+/// let resolved = Resolver::default().resolve("/standup", &store)?;
+/// assert_eq!(resolved.link().unwrap().url, "https://meet.example.com/team");
+/// # Ok::<(), golink::GolinkError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Federated<L, U> {
+    local: L,
+    upstream: U,
+}
+
+impl<L, U> Federated<L, U> {
+    /// Creates a store that checks `local` before `upstream`.
+    #[must_use]
+    pub fn new(local: L, upstream: U) -> Self {
+        Self { local, upstream }
+    }
+
+    /// The store checked first.
+    #[must_use]
+    pub fn local(&self) -> &L {
+        &self.local
+    }
+
+    /// The store checked for links missing locally.
+    #[must_use]
+    pub fn upstream(&self) -> &U {
+        &self.upstream
+    }
+}
+
+/// The error of a [`Federated`] store, saying which of its stores failed.
+///
+/// The failing store's error is the [`source`](StdError::source), and is left out of
+/// the message.
+#[derive(Debug, thiserror::Error)]
+pub enum FederatedError<L, U>
+where
+    L: StdError + 'static,
+    U: StdError + 'static,
+{
+    /// The local store failed.
+    #[error("local store failed")]
+    Local(#[source] L),
+
+    /// The upstream store failed.
+    #[error("upstream store failed")]
+    Upstream(#[source] U),
+}
+
+impl<L: LinkStore, U: LinkStore> LinkStore for Federated<L, U> {
+    type Error = FederatedError<L::Error, U::Error>;
+
+    fn lookup(&self, shortlink: &str) -> Result<Option<Link>, Self::Error> {
+        self.lookup_with_context(shortlink, &RequestContext::default())
+    }
+
+    fn lookup_with_context(
+        &self,
+        shortlink: &str,
+        context: &RequestContext,
+    ) -> Result<Option<Link>, Self::Error> {
+        if let Some(link) = self
+            .local
+            .lookup_with_context(shortlink, context)
+            .map_err(FederatedError::Local)?
+        {
+            return Ok(Some(link));
+        }
+        self.upstream
+            .lookup_with_context(shortlink, context)
+            .map_err(FederatedError::Upstream)
+    }
+
+    fn shortlinks(&self) -> Result<Vec<Candidate>, Self::Error> {
+        let local = self.local.shortlinks().map_err(FederatedError::Local)?;
+        let upstream = self
+            .upstream
+            .shortlinks()
+            .map_err(FederatedError::Upstream)?;
+        Ok(merge(local, upstream))
+    }
+}
+
+impl<L: AsyncLinkStore, U: AsyncLinkStore> AsyncLinkStore for Federated<L, U> {
+    type Error = FederatedError<L::Error, U::Error>;
+
+    async fn lookup(&self, shortlink: &str) -> Result<Option<Link>, Self::Error> {
+        self.lookup_with_context(shortlink, &RequestContext::default())
+            .await
+    }
+
+    async fn lookup_with_context(
+        &self,
+        shortlink: &str,
+        context: &RequestContext,
+    ) -> Result<Option<Link>, Self::Error> {
+        if let Some(link) = self
+            .local
+            .lookup_with_context(shortlink, context)
+            .await
+            .map_err(FederatedError::Local)?
+        {
+            return Ok(Some(link));
+        }
+        self.upstream
+            .lookup_with_context(shortlink, context)
+            .await
+            .map_err(FederatedError::Upstream)
+    }
+
+    async fn shortlinks(&self) -> Result<Vec<Candidate>, Self::Error> {
+        let local = self
+            .local
+            .shortlinks()
+            .await
+            .map_err(FederatedError::Local)?;
+        let upstream = self
+            .upstream
+            .shortlinks()
+            .await
+            .map_err(FederatedError::Upstream)?;
+        Ok(merge(local, upstream))
+    }
+}
+
+/// Lists the local candidates, then the upstream ones that local links don't shadow.
+fn merge(mut local: Vec<Candidate>, upstream: Vec<Candidate>) -> Vec<Candidate> {
+    let shadowed = local
+        .iter()
+        .map(|candidate| candidate.shortlink.clone())
+        .collect::<HashSet<_>>();
+    local.extend(
+        upstream
+            .into_iter()
+            .filter(|candidate| !shadowed.contains(&candidate.shortlink)),
+    );
+    local
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GolinkError, GolinkResolution, Resolver};
+    use itertools::Itertools;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct Unavailable;
+
+    impl fmt::Display for Unavailable {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "store unavailable")
+        }
+    }
+
+    impl StdError for Unavailable {}
+
+    fn url(resolution: Result<GolinkResolution, GolinkError>) -> String {
+        resolution.unwrap().link().unwrap().url.clone()
+    }
+
+    fn stores() -> (HashMap<String, &'static str>, HashMap<String, &'static str>) {
+        let local = HashMap::from([("docs".to_string(), "https://docs.example.com/team")]);
+        let upstream = HashMap::from([
+            ("docs".to_string(), "https://docs.example.com/"),
+            ("wiki".to_string(), "https://wiki.example.com/"),
+        ]);
+        (local, upstream)
+    }
+
+    #[test]
+    fn it_prefers_local_links() {
+        let (local, upstream) = stores();
+        let store = Federated::new(local, upstream);
+        let resolver = Resolver::default();
+
+        assert_eq!(
+            url(resolver.resolve("/docs", &store)),
+            "https://docs.example.com/team"
+        );
+        assert_eq!(
+            url(resolver.resolve("/wiki", &store)),
+            "https://wiki.example.com/"
+        );
+        assert!(matches!(
+            resolver.resolve("/missing", &store),
+            Err(GolinkError::NotFound { .. })
+        ));
+
+        // Upstream links shadowed by local ones aren't listed twice
+        let shortlinks = LinkStore::shortlinks(&store)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| (candidate.shortlink, candidate.link.unwrap().url))
+            .sorted()
+            .collect_vec();
+        assert_eq!(
+            shortlinks,
+            vec![
+                (
+                    "docs".to_string(),
+                    "https://docs.example.com/team".to_string()
+                ),
+                ("wiki".to_string(), "https://wiki.example.com/".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_which_store_failed() {
+        let (local, _) = stores();
+        let failing = |_: &str| Err::<Option<String>, _>(Unavailable);
+
+        let store = Federated::new(local.clone(), failing);
+        assert_eq!(
+            url(Resolver::default().resolve("/docs", &store)),
+            "https://docs.example.com/team"
+        );
+        let Err(GolinkError::LookupFailed(error)) = Resolver::default().resolve("/wiki", &store)
+        else {
+            panic!("expected the upstream lookup to fail");
+        };
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(FederatedError::<std::convert::Infallible, Unavailable>::Upstream(Unavailable))
+        ));
+        let error = error.get_ref();
+        assert_eq!(error.to_string(), "upstream store failed");
+        assert_eq!(error.source().unwrap().to_string(), "store unavailable");
+
+        // A failing local store is never papered over by upstream
+        let store = Federated::new(failing, local);
+        assert!(matches!(
+            Resolver::default().resolve("/docs", &store),
+            Err(GolinkError::LookupFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn async_it_prefers_local_links() {
+        let (local, upstream) = stores();
+        let store = Federated::new(local, upstream);
+
+        let resolved = Resolver::default().resolve_async("/wiki", &store).await;
+        assert_eq!(url(resolved), "https://wiki.example.com/");
+    }
+}
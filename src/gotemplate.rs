@@ -0,0 +1,1403 @@
+//! A subset of Go's `text/template`, as used by the long URLs of
+//! [Tailscale's golink](https://github.com/tailscale/golink).
+//!
+//! Supports `{{ pipeline }}` actions with `|` pipes and parenthesized arguments,
+//! `if`/`else if`/`else`/`end` and `with`/`else`/`end` blocks, comments, `{{-`/`-}}`
+//! whitespace trimming, the `.Path`, `.User` and `.Now` fields of Tailscale's template
+//! data, and the functions Tailscale registers (`PathEscape`, `QueryEscape`,
+//! `TrimPrefix`, `TrimSuffix`, `ToLower`, `ToUpper` and `Match`) alongside Go's
+//! builtins for comparisons, logic and printing. Variables, `range` and nested template
+//! definitions are not supported.
+
+use std::fmt::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use regex::Regex;
+
+use crate::{CompiledTemplate, ExpandEnvironment, GolinkError};
+
+/// Characters escaped by Go's `url.PathEscape`.
+const GO_PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b':')
+    .remove(b'=')
+    .remove(b'@');
+
+/// Characters escaped by Go's `url.QueryEscape`, apart from spaces, which become `+`.
+const GO_QUERY: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b' ');
+
+/// The data a template is executed against: Tailscale's `expandEnv`.
+#[derive(Debug, Clone)]
+pub(crate) struct GoData {
+    /// `.Path`: the remaining path after the shortlink, percent-decoded.
+    pub(crate) path: String,
+
+    /// `.User`: the requesting user, if known. Reading it without one is an error.
+    pub(crate) user: Option<String>,
+
+    /// `.Now`: the time of the request.
+    pub(crate) now: SystemTime,
+}
+
+/// A parsed Go template.
+#[derive(Debug)]
+pub(crate) struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Action(Pipeline),
+    If {
+        condition: Pipeline,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    With {
+        value: Pipeline,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// Commands joined by `|`, each receiving the previous command's value as its last
+/// argument.
+#[derive(Debug)]
+struct Pipeline(Vec<Command>);
+
+#[derive(Debug)]
+struct Command(Vec<Operand>);
+
+#[derive(Debug)]
+enum Operand {
+    Function(Function),
+    /// A value followed by zero or more field or method names: `.`, `.Now.Format`, `$`,
+    /// `(ToLower .Path).Length`.
+    Chain(Base, Vec<String>),
+    String(String),
+    /// A string literal passed as the pattern of `Match`, compiled when parsing.
+    Pattern(Regex),
+    Int(i64),
+    Bool(bool),
+}
+
+#[derive(Debug)]
+enum Base {
+    Dot,
+    Root,
+    Pipeline(Box<Pipeline>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    PathEscape,
+    QueryEscape,
+    TrimPrefix,
+    TrimSuffix,
+    ToLower,
+    ToUpper,
+    Match,
+    And,
+    Or,
+    Not,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Len,
+    Print,
+    Printf,
+    Println,
+}
+
+impl Function {
+    fn named(name: &str) -> Option<Self> {
+        Some(match name {
+            "PathEscape" => Self::PathEscape,
+            "QueryEscape" => Self::QueryEscape,
+            "TrimPrefix" => Self::TrimPrefix,
+            "TrimSuffix" => Self::TrimSuffix,
+            "ToLower" => Self::ToLower,
+            "ToUpper" => Self::ToUpper,
+            "Match" => Self::Match,
+            "and" => Self::And,
+            "or" => Self::Or,
+            "not" => Self::Not,
+            "eq" => Self::Eq,
+            "ne" => Self::Ne,
+            "lt" => Self::Lt,
+            "le" => Self::Le,
+            "gt" => Self::Gt,
+            "ge" => Self::Ge,
+            "len" => Self::Len,
+            "print" => Self::Print,
+            "printf" => Self::Printf,
+            "println" => Self::Println,
+            _ => return None,
+        })
+    }
+}
+
+/// A value produced while executing a template.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    Time(SystemTime),
+    /// The template's data, `.` at the top level and `$` everywhere.
+    Data,
+}
+
+impl Value {
+    fn is_true(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            Value::Int(value) => *value != 0,
+            Value::String(value) => !value.is_empty(),
+            Value::Time(_) | Value::Data => true,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::String(_) => "string",
+            Value::Time(_) => "time.Time",
+            Value::Data => "expandEnv",
+        }
+    }
+
+    fn print(&self) -> Result<String, GolinkError> {
+        match self {
+            Value::Bool(value) => Ok(value.to_string()),
+            Value::Int(value) => Ok(value.to_string()),
+            Value::String(value) => Ok(value.clone()),
+            Value::Time(time) => Ok(format_time(
+                *time,
+                "2006-01-02 15:04:05.999999999 -0700 MST",
+            )),
+            Value::Data => Err(error("can't print the template data")),
+        }
+    }
+}
+
+fn error(message: impl std::fmt::Display) -> GolinkError {
+    GolinkError::TemplateError(format!("Go template: {message}"))
+}
+
+impl Template {
+    pub(crate) fn parse(source: &str) -> Result<Self, GolinkError> {
+        let items = split_items(source)?;
+        let mut parser = Parser { items, position: 0 };
+        let (nodes, end) = parser.parse_list()?;
+        match end {
+            Closer::Eof => Ok(Self { nodes }),
+            Closer::End => Err(error("unexpected {{end}}")),
+            Closer::Else(_) => Err(error("unexpected {{else}}")),
+        }
+    }
+
+    pub(crate) fn execute(&self, data: &GoData) -> Result<String, GolinkError> {
+        let mut output = String::new();
+        Executor { data }.run(&self.nodes, &Value::Data, &mut output)?;
+        Ok(output)
+    }
+}
+
+impl CompiledTemplate for Template {
+    fn render(&self, environment: &ExpandEnvironment) -> Result<String, GolinkError> {
+        self.execute(&GoData {
+            path: environment.path().to_string(),
+            user: environment.user().map(|user| user.login.clone()),
+            now: SystemTime::now(),
+        })
+    }
+}
+
+/// Text between actions, or the contents of an action.
+enum Item {
+    Text(String),
+    Action(String),
+}
+
+/// Splits a template into text and action contents, applying `{{-` and `-}}` trimming
+/// and dropping comments.
+fn split_items(source: &str) -> Result<Vec<Item>, GolinkError> {
+    let mut items = Vec::new();
+    let mut rest = source;
+    let mut trim_next = false;
+
+    while let Some(start) = rest.find("{{") {
+        let mut text = &rest[..start];
+        if trim_next {
+            text = text.trim_start();
+        }
+        let mut action = &rest[start + 2..];
+        if action.starts_with('-') && action[1..].starts_with(char::is_whitespace) {
+            text = text.trim_end();
+            action = &action[1..];
+        }
+        if !text.is_empty() {
+            items.push(Item::Text(text.to_string()));
+        }
+
+        let end = find_action_end(action).ok_or_else(|| error("unclosed action"))?;
+        let mut contents = &action[..end];
+        trim_next = contents.ends_with('-')
+            && contents[..contents.len() - 1].ends_with(char::is_whitespace);
+        if trim_next {
+            contents = &contents[..contents.len() - 1];
+        }
+
+        let contents = contents.trim();
+        if contents.starts_with("/*") {
+            if !contents.ends_with("*/") {
+                return Err(error("unclosed comment"));
+            }
+        } else {
+            items.push(Item::Action(contents.to_string()));
+        }
+        rest = &action[end + 2..];
+    }
+
+    let text = if trim_next { rest.trim_start() } else { rest };
+    if !text.is_empty() {
+        items.push(Item::Text(text.to_string()));
+    }
+    Ok(items)
+}
+
+/// Finds the `}}` that closes an action, skipping over quoted strings.
+fn find_action_end(action: &str) -> Option<usize> {
+    let mut chars = action.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '`' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '`' {
+                        break;
+                    }
+                }
+            }
+            '}' if action[index..].starts_with("}}") => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// What ended a list of nodes.
+enum Closer {
+    Eof,
+    End,
+    /// An `{{else}}`, or an `{{else if ...}}`/`{{else with ...}}` carrying its keyword
+    /// and pipeline.
+    Else(Option<(String, Pipeline)>),
+}
+
+struct Parser {
+    items: Vec<Item>,
+    position: usize,
+}
+
+impl Parser {
+    fn parse_list(&mut self) -> Result<(Vec<Node>, Closer), GolinkError> {
+        let mut nodes = Vec::new();
+        while let Some(item) = self.items.get(self.position) {
+            self.position += 1;
+            let action = match item {
+                Item::Text(text) => {
+                    nodes.push(Node::Text(text.clone()));
+                    continue;
+                }
+                Item::Action(action) => action.clone(),
+            };
+
+            let (keyword, rest) = action
+                .split_once(char::is_whitespace)
+                .map_or((action.as_str(), ""), |(keyword, rest)| {
+                    (keyword, rest.trim())
+                });
+            match keyword {
+                "if" | "with" => {
+                    let node = self.parse_block(keyword, parse_pipeline(rest)?)?;
+                    nodes.push(node);
+                }
+                "else" => {
+                    let chained = match rest.split_once(char::is_whitespace) {
+                        None if rest.is_empty() => None,
+                        Some((keyword @ ("if" | "with"), rest)) => {
+                            Some((keyword.to_string(), parse_pipeline(rest)?))
+                        }
+                        _ => return Err(error(format!("unexpected \"{rest}\" in else"))),
+                    };
+                    return Ok((nodes, Closer::Else(chained)));
+                }
+                "end" if rest.is_empty() => return Ok((nodes, Closer::End)),
+                "range" | "define" | "template" | "block" | "break" | "continue" => {
+                    return Err(error(format!("{{{{{keyword}}}}} is not supported")));
+                }
+                _ => nodes.push(Node::Action(parse_pipeline(&action)?)),
+            }
+        }
+        Ok((nodes, Closer::Eof))
+    }
+
+    /// Parses the body of an `if` or `with` block whose opening action has been read.
+    fn parse_block(&mut self, keyword: &str, pipeline: Pipeline) -> Result<Node, GolinkError> {
+        let (then, end) = self.parse_list()?;
+        let otherwise = match end {
+            Closer::Eof => return Err(error(format!("unexpected EOF in {{{{{keyword}}}}}"))),
+            Closer::End => Vec::new(),
+            Closer::Else(None) => match self.parse_list()? {
+                (otherwise, Closer::End) => otherwise,
+                _ => return Err(error("expected {{end}} after {{else}}")),
+            },
+            // `{{else if}}` shares the `{{end}}` of the block it continues
+            Closer::Else(Some((keyword, pipeline))) => vec![self.parse_block(&keyword, pipeline)?],
+        };
+
+        Ok(match keyword {
+            "if" => Node::If {
+                condition: pipeline,
+                then,
+                otherwise,
+            },
+            _ => Node::With {
+                value: pipeline,
+                then,
+                otherwise,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Field(String),
+    Dot,
+    Dollar,
+    Identifier(String),
+    String(String),
+    Int(i64),
+    Bool(bool),
+    LeftParen,
+    RightParen,
+    Pipe,
+}
+
+/// Splits the contents of an action into tokens, each paired with whether whitespace
+/// came before it.
+fn tokenize(action: &str) -> Result<Vec<(Token, bool)>, GolinkError> {
+    let mut tokens = Vec::new();
+    let mut chars = action.char_indices().peekable();
+    let mut spaced = true;
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            spaced = true;
+            continue;
+        }
+
+        let word = |chars: &mut std::iter::Peekable<std::str::CharIndices>| {
+            let mut word = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            word
+        };
+
+        let token = match c {
+            '.' => {
+                chars.next();
+                let name = word(&mut chars);
+                if name.is_empty() {
+                    Token::Dot
+                } else {
+                    Token::Field(name)
+                }
+            }
+            '$' => {
+                chars.next();
+                if !word(&mut chars).is_empty() {
+                    return Err(error("variables are not supported"));
+                }
+                Token::Dollar
+            }
+            '(' | ')' | '|' => {
+                chars.next();
+                match c {
+                    '(' => Token::LeftParen,
+                    ')' => Token::RightParen,
+                    _ => Token::Pipe,
+                }
+            }
+            '"' => {
+                chars.next();
+                Token::String(quoted_string(&mut chars)?)
+            }
+            '`' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '`')) => break,
+                        Some((_, c)) => string.push(c),
+                        None => return Err(error("unterminated raw string")),
+                    }
+                }
+                Token::String(string)
+            }
+            ':' | '=' => return Err(error("variables are not supported")),
+            c if c.is_ascii_digit() || c == '-' || c == '+' => {
+                chars.next();
+                let digits = format!("{c}{}", word(&mut chars));
+                let value = digits
+                    .strip_prefix("0x")
+                    .map_or_else(|| digits.parse(), |hex| i64::from_str_radix(hex, 16));
+                Token::Int(value.map_err(|_| error(format!("bad number syntax: {digits}")))?)
+            }
+            c if c.is_alphabetic() || c == '_' => match word(&mut chars).as_str() {
+                "true" => Token::Bool(true),
+                "false" => Token::Bool(false),
+                "nil" => return Err(error("nil is not supported")),
+                name => Token::Identifier(name.to_string()),
+            },
+            c => {
+                return Err(error(format!(
+                    "unexpected {c:?} in \"{}\"",
+                    &action[..start + c.len_utf8()]
+                )));
+            }
+        };
+
+        tokens.push((token, spaced));
+        spaced = false;
+    }
+
+    Ok(tokens)
+}
+
+/// Reads the rest of a double-quoted Go string literal, handling its escapes.
+fn quoted_string(chars: &mut impl Iterator<Item = (usize, char)>) -> Result<String, GolinkError> {
+    let mut string = String::new();
+    loop {
+        let c = match chars.next() {
+            Some((_, '"')) => return Ok(string),
+            Some((_, '\\')) => match chars.next().map(|(_, c)| c) {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('\\') => '\\',
+                Some('"') => '"',
+                Some('\'') => '\'',
+                Some(escape @ ('x' | 'u' | 'U')) => {
+                    let width = match escape {
+                        'x' => 2,
+                        'u' => 4,
+                        _ => 8,
+                    };
+                    let hex = chars
+                        .by_ref()
+                        .take(width)
+                        .map(|(_, c)| c)
+                        .collect::<String>();
+                    u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| error(format!("invalid escape \\{escape}{hex}")))?
+                }
+                Some(c) => return Err(error(format!("unknown escape sequence \\{c}"))),
+                None => return Err(error("unterminated quoted string")),
+            },
+            Some((_, c)) => c,
+            None => return Err(error("unterminated quoted string")),
+        };
+        string.push(c);
+    }
+}
+
+fn parse_pipeline(action: &str) -> Result<Pipeline, GolinkError> {
+    let tokens = tokenize(action)?;
+    let mut position = 0;
+    let pipeline = parse_tokens(&tokens, &mut position)?;
+    match tokens.get(position) {
+        None => Ok(pipeline),
+        Some(_) => Err(error(format!("unexpected \")\" in \"{action}\""))),
+    }
+}
+
+/// Parses a pipeline, stopping before an unmatched `)`.
+fn parse_tokens(tokens: &[(Token, bool)], position: &mut usize) -> Result<Pipeline, GolinkError> {
+    let mut commands = Vec::new();
+    let mut operands = Vec::new();
+
+    while let Some((token, _)) = tokens.get(*position) {
+        *position += 1;
+        let operand = match token {
+            Token::Pipe => {
+                if operands.is_empty() {
+                    return Err(error("missing command before |"));
+                }
+                commands.push(parse_command(std::mem::take(&mut operands))?);
+                continue;
+            }
+            Token::RightParen => {
+                *position -= 1;
+                break;
+            }
+            Token::LeftParen => {
+                let pipeline = parse_tokens(tokens, position)?;
+                if tokens.get(*position).map(|(token, _)| token) != Some(&Token::RightParen) {
+                    return Err(error("unclosed left paren"));
+                }
+                *position += 1;
+                Operand::Chain(
+                    Base::Pipeline(Box::new(pipeline)),
+                    chained_fields(tokens, position),
+                )
+            }
+            Token::Field(name) => {
+                let mut fields = vec![name.clone()];
+                fields.extend(chained_fields(tokens, position));
+                Operand::Chain(Base::Dot, fields)
+            }
+            Token::Dot => Operand::Chain(Base::Dot, Vec::new()),
+            Token::Dollar => Operand::Chain(Base::Root, chained_fields(tokens, position)),
+            Token::Identifier(name) => Operand::Function(
+                Function::named(name)
+                    .ok_or_else(|| error(format!("function \"{name}\" not defined")))?,
+            ),
+            Token::String(value) => Operand::String(value.clone()),
+            Token::Int(value) => Operand::Int(*value),
+            Token::Bool(value) => Operand::Bool(*value),
+        };
+        operands.push(operand);
+    }
+
+    if operands.is_empty() {
+        return Err(error(if commands.is_empty() {
+            "missing value for command"
+        } else {
+            "missing command after |"
+        }));
+    }
+    commands.push(parse_command(operands)?);
+    Ok(Pipeline(commands))
+}
+
+/// Collects field names written directly after a value, like `.Format` in `$.Now.Format`.
+fn chained_fields(tokens: &[(Token, bool)], position: &mut usize) -> Vec<String> {
+    let mut fields = Vec::new();
+    while let Some((Token::Field(name), false)) = tokens.get(*position) {
+        fields.push(name.clone());
+        *position += 1;
+    }
+    fields
+}
+
+fn parse_command(mut operands: Vec<Operand>) -> Result<Command, GolinkError> {
+    if operands.len() > 1 && !matches!(operands[0], Operand::Function(_) | Operand::Chain(..)) {
+        return Err(error("can't give arguments to a non-function"));
+    }
+
+    if let [
+        Operand::Function(Function::Match),
+        Operand::String(pattern),
+        ..,
+    ] = operands.as_slice()
+    {
+        let regex = Regex::new(pattern).map_err(|e| error(format!("invalid pattern: {e}")))?;
+        operands[1] = Operand::Pattern(regex);
+    }
+    Ok(Command(operands))
+}
+
+struct Executor<'a> {
+    data: &'a GoData,
+}
+
+impl Executor<'_> {
+    fn run(&self, nodes: &[Node], dot: &Value, output: &mut String) -> Result<(), GolinkError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => output.push_str(text),
+                Node::Action(pipeline) => {
+                    output.push_str(&self.pipeline(pipeline, dot)?.print()?);
+                }
+                Node::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    let branch = if self.pipeline(condition, dot)?.is_true() {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    self.run(branch, dot, output)?;
+                }
+                Node::With {
+                    value,
+                    then,
+                    otherwise,
+                } => {
+                    let value = self.pipeline(value, dot)?;
+                    if value.is_true() {
+                        self.run(then, &value, output)?;
+                    } else {
+                        self.run(otherwise, dot, output)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn pipeline(&self, pipeline: &Pipeline, dot: &Value) -> Result<Value, GolinkError> {
+        let mut value = None;
+        for command in &pipeline.0 {
+            value = Some(self.command(command, dot, value)?);
+        }
+        value.ok_or_else(|| error("empty pipeline"))
+    }
+
+    fn command(
+        &self,
+        command: &Command,
+        dot: &Value,
+        piped: Option<Value>,
+    ) -> Result<Value, GolinkError> {
+        let (first, rest) = command
+            .0
+            .split_first()
+            .ok_or_else(|| error("empty command"))?;
+
+        match first {
+            Operand::Function(function) => self.call(*function, rest, piped, dot),
+            Operand::Chain(base, fields) if !rest.is_empty() || piped.is_some() => {
+                // A method call with arguments, like `.Now.Format "2006-01-02"`
+                let Some((method, receiver_fields)) = fields.split_last() else {
+                    return Err(error("can't give arguments to a non-function"));
+                };
+                let receiver = self.chain(base, receiver_fields, dot)?;
+                let mut args = rest
+                    .iter()
+                    .map(|operand| self.operand(operand, dot))
+                    .collect::<Result<Vec<_>, _>>()?;
+                args.extend(piped);
+                self.field(&receiver, method, &args)
+            }
+            operand => self.operand(operand, dot),
+        }
+    }
+
+    fn operand(&self, operand: &Operand, dot: &Value) -> Result<Value, GolinkError> {
+        match operand {
+            Operand::Function(function) => self.call(*function, &[], None, dot),
+            Operand::Chain(base, fields) => self.chain(base, fields, dot),
+            Operand::String(value) => Ok(Value::String(value.clone())),
+            Operand::Pattern(regex) => Ok(Value::String(regex.as_str().to_string())),
+            Operand::Int(value) => Ok(Value::Int(*value)),
+            Operand::Bool(value) => Ok(Value::Bool(*value)),
+        }
+    }
+
+    fn chain(&self, base: &Base, fields: &[String], dot: &Value) -> Result<Value, GolinkError> {
+        let mut value = match base {
+            Base::Dot => dot.clone(),
+            Base::Root => Value::Data,
+            Base::Pipeline(pipeline) => self.pipeline(pipeline, dot)?,
+        };
+        for field in fields {
+            value = self.field(&value, field, &[])?;
+        }
+        Ok(value)
+    }
+
+    /// Reads a field, or calls a method, of `receiver`.
+    fn field(&self, receiver: &Value, name: &str, args: &[Value]) -> Result<Value, GolinkError> {
+        let value = match (receiver, name) {
+            (Value::Data, "Path") => Value::String(self.data.path.clone()),
+            (Value::Data, "Now") => Value::Time(self.data.now),
+            (Value::Data, "User") => {
+                let user = self.data.user.clone();
+                Value::String(user.ok_or_else(|| error("no user"))?)
+            }
+            (Value::Time(time), "Format") => {
+                let [Value::String(layout)] = args else {
+                    return Err(error("wrong arguments for Format: want a layout string"));
+                };
+                return Ok(Value::String(format_time(*time, layout)));
+            }
+            (Value::Time(time), _) => {
+                let date = Date::new(*time);
+                match name {
+                    "Unix" => Value::Int(date.seconds),
+                    "Year" => Value::Int(date.year),
+                    "YearDay" => Value::Int(date.year_day),
+                    "Month" => Value::String(MONTHS[date.month as usize - 1].to_string()),
+                    "Day" => Value::Int(date.day),
+                    "Weekday" => Value::String(WEEKDAYS[date.weekday as usize].to_string()),
+                    "Hour" => Value::Int(date.hour),
+                    "Minute" => Value::Int(date.minute),
+                    "Second" => Value::Int(date.second),
+                    "UTC" | "Local" => Value::Time(*time),
+                    _ => {
+                        return Err(error(format!(
+                            "can't evaluate field {name} in type time.Time"
+                        )));
+                    }
+                }
+            }
+            _ => {
+                return Err(error(format!(
+                    "can't evaluate field {name} in type {}",
+                    receiver.type_name()
+                )));
+            }
+        };
+
+        if args.is_empty() {
+            Ok(value)
+        } else {
+            Err(error(format!("{name} is not a method but has arguments")))
+        }
+    }
+
+    fn call(
+        &self,
+        function: Function,
+        operands: &[Operand],
+        piped: Option<Value>,
+        dot: &Value,
+    ) -> Result<Value, GolinkError> {
+        // `and` and `or` evaluate their arguments lazily, like Go's
+        if matches!(function, Function::And | Function::Or) {
+            let count = operands.len() + usize::from(piped.is_some());
+            if count == 0 {
+                return Err(error("wrong number of args: want at least 1 got 0"));
+            }
+            let mut values = operands
+                .iter()
+                .map(|operand| self.operand(operand, dot))
+                .chain(piped.map(Ok));
+            let mut last = Value::Bool(false);
+            for value in values.by_ref() {
+                last = value?;
+                if last.is_true() == (function == Function::Or) {
+                    break;
+                }
+            }
+            return Ok(last);
+        }
+
+        let mut args = operands
+            .iter()
+            .map(|operand| self.operand(operand, dot))
+            .collect::<Result<Vec<_>, _>>()?;
+        args.extend(piped);
+
+        let want = |count: usize| {
+            if args.len() == count {
+                Ok(())
+            } else {
+                Err(error(format!(
+                    "wrong number of args for {function:?}: want {count} got {}",
+                    args.len()
+                )))
+            }
+        };
+
+        Ok(match function {
+            Function::PathEscape => {
+                want(1)?;
+                Value::String(utf8_percent_encode(string(&args[0])?, GO_PATH_SEGMENT).to_string())
+            }
+            Function::QueryEscape => {
+                want(1)?;
+                Value::String(
+                    utf8_percent_encode(string(&args[0])?, GO_QUERY)
+                        .to_string()
+                        .replace(' ', "+"),
+                )
+            }
+            Function::TrimPrefix => {
+                want(2)?;
+                let (value, prefix) = (string(&args[0])?, string(&args[1])?);
+                Value::String(value.strip_prefix(prefix).unwrap_or(value).to_string())
+            }
+            Function::TrimSuffix => {
+                want(2)?;
+                let (value, suffix) = (string(&args[0])?, string(&args[1])?);
+                Value::String(value.strip_suffix(suffix).unwrap_or(value).to_string())
+            }
+            Function::ToLower => {
+                want(1)?;
+                Value::String(string(&args[0])?.to_lowercase())
+            }
+            Function::ToUpper => {
+                want(1)?;
+                Value::String(string(&args[0])?.to_uppercase())
+            }
+            Function::Match => {
+                want(2)?;
+                let value = string(&args[1])?;
+                let is_match = match operands.first() {
+                    Some(Operand::Pattern(regex)) => regex.is_match(value),
+                    _ => Regex::new(string(&args[0])?)
+                        .map_err(|e| error(format!("invalid pattern: {e}")))?
+                        .is_match(value),
+                };
+                Value::Bool(is_match)
+            }
+            Function::Not => {
+                want(1)?;
+                Value::Bool(!args[0].is_true())
+            }
+            Function::Eq => {
+                if args.len() < 2 {
+                    return Err(error("missing argument for comparison"));
+                }
+                let mut equal = false;
+                for other in &args[1..] {
+                    equal |= equals(&args[0], other)?;
+                }
+                Value::Bool(equal)
+            }
+            Function::Ne => {
+                want(2)?;
+                Value::Bool(!equals(&args[0], &args[1])?)
+            }
+            Function::Lt | Function::Le | Function::Gt | Function::Ge => {
+                want(2)?;
+                let ordering = compare(&args[0], &args[1])?;
+                Value::Bool(match function {
+                    Function::Lt => ordering.is_lt(),
+                    Function::Le => ordering.is_le(),
+                    Function::Gt => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                })
+            }
+            Function::Len => {
+                want(1)?;
+                let length = string(&args[0])?.len();
+                Value::Int(i64::try_from(length).unwrap_or(i64::MAX))
+            }
+            Function::Print => Value::String(sprint(&args, false)?),
+            Function::Println => Value::String(sprint(&args, true)? + "\n"),
+            Function::Printf => {
+                let (format, args) = args.split_first().ok_or_else(|| {
+                    error("wrong number of args for printf: want at least 1 got 0")
+                })?;
+                Value::String(sprintf(string(format)?, args)?)
+            }
+            Function::And | Function::Or => unreachable!("handled above"),
+        })
+    }
+}
+
+fn string(value: &Value) -> Result<&str, GolinkError> {
+    match value {
+        Value::String(value) => Ok(value),
+        other => Err(error(format!(
+            "wrong type for value; expected string; got {}",
+            other.type_name()
+        ))),
+    }
+}
+
+/// Compares two basic values of the same type for equality, as Go's `eq` does.
+fn equals(a: &Value, b: &Value) -> Result<bool, GolinkError> {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+        _ => compare(a, b).map(std::cmp::Ordering::is_eq),
+    }
+}
+
+/// Orders two integers or two strings, as Go's `lt`, `le`, `gt` and `ge` do.
+fn compare(a: &Value, b: &Value) -> Result<std::cmp::Ordering, GolinkError> {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Ok(a.cmp(b)),
+        (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        _ => Err(error(format!(
+            "incompatible types for comparison: {} and {}",
+            a.type_name(),
+            b.type_name()
+        ))),
+    }
+}
+
+/// Formats values like Go's `fmt.Sprint` (or `fmt.Sprintln` when `spaced`).
+fn sprint(args: &[Value], spaced: bool) -> Result<String, GolinkError> {
+    let mut output = String::new();
+    for (index, value) in args.iter().enumerate() {
+        let between_strings = index > 0
+            && (matches!(value, Value::String(_)) || matches!(args[index - 1], Value::String(_)));
+        if index > 0 && (spaced || !between_strings) {
+            output.push(' ');
+        }
+        output.push_str(&value.print()?);
+    }
+    Ok(output)
+}
+
+/// Formats values like Go's `fmt.Sprintf`, for the `%s`, `%v`, `%d`, `%q` and `%%` verbs.
+fn sprintf(format: &str, args: &[Value]) -> Result<String, GolinkError> {
+    let mut output = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        let verb = chars.next().ok_or_else(|| error("printf: missing verb"))?;
+        if verb == '%' {
+            output.push('%');
+            continue;
+        }
+        let Some(value) = args.next() else {
+            let _ = write!(output, "%!{verb}(MISSING)");
+            continue;
+        };
+        match verb {
+            's' | 'v' | 'd' => output.push_str(&value.print()?),
+            'q' => {
+                let _ = write!(output, "{:?}", value.print()?);
+            }
+            _ => return Err(error(format!("printf: unsupported verb %{verb}"))),
+        }
+    }
+    Ok(output)
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// The calendar fields of a time, in UTC.
+struct Date {
+    seconds: i64,
+    nanos: u32,
+    year: i64,
+    month: i64,
+    day: i64,
+    year_day: i64,
+    weekday: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+}
+
+impl Date {
+    fn new(time: SystemTime) -> Self {
+        let (seconds, nanos) = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => (since.as_secs().cast_signed(), since.subsec_nanos()),
+            Err(before) => {
+                let before = before.duration();
+                let whole = before.subsec_nanos() == 0;
+                let seconds = -before.as_secs().cast_signed() - i64::from(!whole);
+                let nanos = if whole {
+                    0
+                } else {
+                    1_000_000_000 - before.subsec_nanos()
+                };
+                (seconds, nanos)
+            }
+        };
+
+        let days = seconds.div_euclid(86_400);
+        let second_of_day = seconds.rem_euclid(86_400);
+
+        // Howard Hinnant's days-to-civil algorithm
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        let is_leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let year_day = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334][month as usize - 1]
+            + day
+            + i64::from(is_leap && month > 2);
+
+        Self {
+            seconds,
+            nanos,
+            year,
+            month,
+            day,
+            year_day,
+            weekday: (days + 4).rem_euclid(7),
+            hour: second_of_day / 3600,
+            minute: second_of_day % 3600 / 60,
+            second: second_of_day % 60,
+        }
+    }
+}
+
+/// Formats a time in UTC using a Go reference-time layout, like `"2006-01-02"`.
+fn format_time(time: SystemTime, layout: &str) -> String {
+    let date = Date::new(time);
+    let hour12 = if date.hour % 12 == 0 {
+        12
+    } else {
+        date.hour % 12
+    };
+    let month = MONTHS[date.month as usize - 1];
+    let weekday = WEEKDAYS[date.weekday as usize];
+
+    let chunks: &[(&str, String)] = &[
+        ("January", month.to_string()),
+        ("Jan", month[..3].to_string()),
+        ("Monday", weekday.to_string()),
+        ("Mon", weekday[..3].to_string()),
+        ("MST", "UTC".to_string()),
+        ("2006", format!("{:04}", date.year)),
+        ("002", format!("{:03}", date.year_day)),
+        ("__2", format!("{:>3}", date.year_day)),
+        ("_2", format!("{:>2}", date.day)),
+        ("01", format!("{:02}", date.month)),
+        ("02", format!("{:02}", date.day)),
+        ("03", format!("{hour12:02}")),
+        ("04", format!("{:02}", date.minute)),
+        ("05", format!("{:02}", date.second)),
+        ("06", format!("{:02}", date.year.rem_euclid(100))),
+        ("15", format!("{:02}", date.hour)),
+        ("1", date.month.to_string()),
+        ("2", date.day.to_string()),
+        ("3", hour12.to_string()),
+        ("4", date.minute.to_string()),
+        ("5", date.second.to_string()),
+        ("PM", if date.hour >= 12 { "PM" } else { "AM" }.to_string()),
+        ("pm", if date.hour >= 12 { "pm" } else { "am" }.to_string()),
+        ("-07:00:00", "+00:00:00".to_string()),
+        ("-070000", "+000000".to_string()),
+        ("-07:00", "+00:00".to_string()),
+        ("-0700", "+0000".to_string()),
+        ("-07", "+00".to_string()),
+        ("Z07:00:00", "Z".to_string()),
+        ("Z070000", "Z".to_string()),
+        ("Z07:00", "Z".to_string()),
+        ("Z0700", "Z".to_string()),
+        ("Z07", "Z".to_string()),
+    ];
+
+    let mut output = String::new();
+    let mut rest = layout;
+    'layout: while let Some(c) = rest.chars().next() {
+        // Fractional seconds: `.000` keeps trailing zeros, `.999` trims them
+        if matches!(c, '.' | ',') {
+            let digits = &rest[1..];
+            let width = digits
+                .find(|d| d != '0')
+                .unwrap_or(digits.len())
+                .max(digits.find(|d| d != '9').unwrap_or(digits.len()));
+            let run = &digits[..width];
+            let uniform =
+                !run.is_empty() && (run.chars().all(|d| d == '0') || run.chars().all(|d| d == '9'));
+            if uniform && !digits[width..].starts_with(|d: char| d.is_ascii_digit()) {
+                let fraction = format!("{:09}", date.nanos);
+                let fraction = &fraction[..width.min(9)];
+                if run.starts_with('0') {
+                    output.push(c);
+                    output.push_str(fraction);
+                } else if !fraction.trim_end_matches('0').is_empty() {
+                    output.push(c);
+                    output.push_str(fraction.trim_end_matches('0'));
+                }
+                rest = &digits[width..];
+                continue;
+            }
+        }
+
+        for (chunk, value) in chunks {
+            if let Some(after) = rest.strip_prefix(chunk) {
+                output.push_str(value);
+                rest = after;
+                continue 'layout;
+            }
+        }
+
+        output.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    /// 2022-06-02 15:04:05.25 UTC, a Thursday.
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_654_182_245_250)
+    }
+
+    fn execute(template: &str, path: &str, user: Option<&str>) -> Result<String, GolinkError> {
+        Template::parse(template)?.execute(&GoData {
+            path: path.to_string(),
+            user: user.map(str::to_string),
+            now: now(),
+        })
+    }
+
+    #[test]
+    fn it_expands_tailscale_examples() {
+        let corpus = [
+            ("http://host.com/{{.Path}}", "", "http://host.com/"),
+            ("http://host.com/{{.Path}}", "bar", "http://host.com/bar"),
+            (
+                "http://host.com/{{if .Path}}search?q={{QueryEscape .Path}}{{end}}",
+                "",
+                "http://host.com/",
+            ),
+            (
+                "http://host.com/{{if .Path}}search?q={{QueryEscape .Path}}{{end}}",
+                "a b&c",
+                "http://host.com/search?q=a+b%26c",
+            ),
+            (
+                "http://host.com/{{.User}}/{{.Path}}",
+                "bar",
+                "http://host.com/foo@example.com/bar",
+            ),
+            (
+                "http://host.com/{{if .Path}}{{.Path}}{{else}}{{.User}}{{end}}",
+                "",
+                "http://host.com/foo@example.com",
+            ),
+            (
+                r#"http://host.com/{{if Match "\\d+" .Path}}id/{{.Path}}{{else}}search/{{.Path}}{{end}}"#,
+                "123",
+                "http://host.com/id/123",
+            ),
+            (
+                r#"http://host.com/{{if Match "\\d+" .Path}}id/{{.Path}}{{else}}search/{{.Path}}{{end}}"#,
+                "foo",
+                "http://host.com/search/foo",
+            ),
+            (
+                r#"https://roamresearch.com/#/app/ts-corp/page/{{.Now.Format "01-02-2006"}}"#,
+                "",
+                "https://roamresearch.com/#/app/ts-corp/page/06-02-2022",
+            ),
+            (
+                "http://host.com/{{PathEscape .Path}}",
+                "a/b c",
+                "http://host.com/a%2Fb%20c",
+            ),
+            (
+                r#"http://host.com/{{TrimPrefix .Path "BUG-"}}"#,
+                "BUG-123",
+                "http://host.com/123",
+            ),
+            (
+                r#"http://host.com/{{TrimSuffix .Path "/"}}"#,
+                "foo/",
+                "http://host.com/foo",
+            ),
+            (
+                "http://host.com/{{ToLower .Path}}",
+                "BAR",
+                "http://host.com/bar",
+            ),
+            (
+                "http://host.com/{{ToUpper .Path}}",
+                "bar",
+                "http://host.com/BAR",
+            ),
+            (
+                "http://host.com/{{with .Path}}{{.}}{{else}}home{{end}}",
+                "",
+                "http://host.com/home",
+            ),
+        ];
+
+        for (template, path, expected) in corpus {
+            assert_eq!(
+                execute(template, path, Some("foo@example.com")),
+                Ok(expected.to_string()),
+                "{template} with {path:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_fails_without_a_user() {
+        assert_eq!(
+            execute("http://host.com/{{.User}}", "", None),
+            Err(GolinkError::TemplateError(
+                "Go template: no user".to_string()
+            ))
+        );
+        assert_eq!(
+            execute("http://host.com/{{or .Path .User}}", "bar", None),
+            Ok("http://host.com/bar".to_string())
+        );
+    }
+
+    #[test]
+    fn it_supports_pipes_parentheses_and_builtins() {
+        let cases = [
+            (r#"{{.Path | ToUpper | printf "%s!"}}"#, "HI!"),
+            (r#"{{if eq .Path "a" "hi"}}yes{{end}}"#, "yes"),
+            (
+                r#"{{if and .Path (not (eq (len .Path) 3))}}yes{{end}}"#,
+                "yes",
+            ),
+            ("{{ .Path -}}  \n  / {{- .Path }}", "hi/hi"),
+            ("a{{/* a comment */}}b", "ab"),
+            (
+                r#"{{$.Now.Year}}-{{.Now.YearDay}} {{.Now.Weekday}} {{print 1 2 "x"}}"#,
+                "2022-153 Thursday 1 2x",
+            ),
+            ("{{`raw\\n`}}{{\"\\u00e9\"}}", "raw\\né"),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(
+                execute(template, "hi", None),
+                Ok(expected.to_string()),
+                "{template}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_formats_times_like_go() {
+        let cases = [
+            ("2006-01-02T15:04:05Z07:00", "2022-06-02T15:04:05Z"),
+            (
+                "Mon Jan _2 15:04:05 MST 2006",
+                "Thu Jun  2 15:04:05 UTC 2022",
+            ),
+            (
+                "Monday, January 2, 2006 3:04PM",
+                "Thursday, June 2, 2022 3:04PM",
+            ),
+            (
+                "06/1/2 03:04:05.000 pm -0700",
+                "22/6/2 03:04:05.250 pm +0000",
+            ),
+            ("15:04:05.999999999", "15:04:05.25"),
+            ("day 002", "day 153"),
+        ];
+        for (layout, expected) in cases {
+            assert_eq!(format_time(now(), layout), expected, "{layout}");
+        }
+        assert_eq!(
+            format_time(UNIX_EPOCH - Duration::from_secs(1), "2006-01-02 15:04:05"),
+            "1969-12-31 23:59:59"
+        );
+        assert_eq!(
+            execute("{{.Now}}", "", None),
+            Ok("2022-06-02 15:04:05.25 +0000 UTC".to_string())
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_templates() {
+        for template in [
+            "{{.Path",
+            "{{if .Path}}",
+            "{{end}}",
+            "{{if .Path}}{{else}}{{else}}{{end}}",
+            "{{Foo .Path}}",
+            "{{range .Path}}{{end}}",
+            "{{$x := .Path}}",
+            r#"{{Match "(" .Path}}"#,
+            "{{(.Path}}",
+            "{{.Path)}}",
+        ] {
+            assert!(
+                matches!(
+                    Template::parse(template),
+                    Err(GolinkError::TemplateError(_))
+                ),
+                "{template}"
+            );
+        }
+
+        for template in [
+            "{{.Nope}}",
+            "{{.Path.Nope}}",
+            "{{ToLower 1}}",
+            "{{eq .Path 1}}",
+        ] {
+            assert!(
+                matches!(
+                    execute(template, "a", None),
+                    Err(GolinkError::TemplateError(_))
+                ),
+                "{template}"
+            );
+        }
+    }
+}
@@ -0,0 +1,673 @@
+//! Looking links up on another go link service over HTTP.
+
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+use percent_encoding::utf8_percent_encode;
+use ureq::Agent;
+use ureq::http::StatusCode;
+
+use crate::cache::LinkCache;
+use crate::expand::PATH_SEGMENT;
+use crate::{AsyncLinkStore, Link, LinkKind, LinkStore, RequestContext};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_HIT_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_MISS_TTL: Duration = Duration::from_secs(10);
+const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+/// A [`LinkStore`] that looks links up on another go link service over HTTP.
+///
+/// Links found upstream have their [`source`](Link::source) set to the upstream's base
+/// URL (unless it got them from further upstream itself), so resolutions record which
+/// instance answered.
+///
+/// Both hits and misses are cached, for a minute and ten seconds by default, so a
+/// popular upstream link or a burst of typos costs one request. Failed requests are
+/// never cached. Requests time out after two seconds.
+///
+/// `HttpStore` is both a [`LinkStore`], whose lookups block the calling thread, and an
+/// [`AsyncLinkStore`], whose lookups make their request on a thread of their own so
+/// they don't block the executor. Combine it with your own store using
+/// [`Federated`](crate::Federated) so only links missing locally are fetched.
+///
+/// Requires the `http` feature.
+///
+/// # Protocol
+///
+/// The upstream has to speak this crate's metadata protocol, which other go link
+/// servers don't unless they implement it. Each lookup is a metadata request for the
+/// shortlink, `GET {base_url}/{shortlink}+` with `Accept: application/json` (see
+/// [`metadata_suffix`](Self::metadata_suffix)), which the upstream answers with:
+///
+/// - `200 OK` and the stored link as a JSON object, in the format [`Link`] serializes
+///   to, like `{"url": "https://meet.example.com/all-hands", "owner": "eng"}`. Only
+///   `url` is required.
+/// - `404 Not Found` or `410 Gone` if it doesn't have the link, or `401 Unauthorized`
+///   or `403 Forbidden` if the requesting user may not see it. Both are reported as
+///   not found.
+///
+/// Any other response fails the lookup with [`HttpStoreError`]. The fields of the link
+/// are used as follows:
+///
+/// - `url` is expanded here like a local link's, with `kind` and `signature`, except
+///   that an alias like `go/other` is pointed at the upstream, as
+///   `{base_url}/other`, so that it's resolved there rather than against local links.
+/// - `access` is enforced here as well as upstream, so it can only narrow who resolves
+///   the link.
+/// - `source` is kept if set, and set to `base_url` otherwise.
+/// - `redirect_kind`, `visibility`, `owner`, `description`, `tags`, `created_at` and
+///   `updated_at` are passed through as they are.
+///
+/// The upstream only learns who's asking if [`user_header`](Self::user_header) is set.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::collections::HashMap;
+/// use std::time::Duration;
+/// use golink::{Federated, HttpStore, Resolver};
+///
+/// let team: HashMap<String, String> = HashMap::new();
+/// let company = HttpStore::new("https://go.example.com")
+///     .timeout(Duration::from_millis(500))
+///     .miss_ttl(Duration::from_secs(60));
+/// let store = Federated::new(team, company);
+///
+/// let resolved = Resolver::default().resolve("/standup", &store)?;
+/// assert_eq!(
+///     resolved.link().unwrap().source.as_deref(),
+///     Some("https://go.example.com")
+/// );
+/// # Ok::<(), golink::GolinkError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpStore {
+    base_url: String,
+    metadata_suffix: String,
+    user_header: Option<String>,
+    agent: Agent,
+    hit_ttl: Duration,
+    miss_ttl: Duration,
+    cache: Arc<LinkCache<CacheKey>>,
+}
+
+impl HttpStore {
+    /// Creates a store that looks links up on the go link service at `base_url`,
+    /// e.g. `https://go.example.com`.
+    #[must_use]
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            metadata_suffix: "+".to_string(),
+            user_header: None,
+            agent: agent(DEFAULT_TIMEOUT),
+            hit_ttl: DEFAULT_HIT_TTL,
+            miss_ttl: DEFAULT_MISS_TTL,
+            cache: Arc::new(LinkCache::new(DEFAULT_CACHE_CAPACITY)),
+        }
+    }
+
+    /// Sets the suffix the upstream uses for metadata requests. Defaults to `+`.
+    #[must_use]
+    pub fn metadata_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.metadata_suffix = suffix.into();
+        self
+    }
+
+    /// Sends the login of the requesting user to the upstream in the header `name`,
+    /// e.g. `X-Forwarded-User`, so it can resolve links restricted to them. Lookups are
+    /// then cached separately for each user. Off by default.
+    ///
+    /// The upstream takes the header's word for who's asking, so it should only accept
+    /// it from instances it trusts.
+    #[must_use]
+    pub fn user_header(mut self, name: impl Into<String>) -> Self {
+        self.user_header = Some(name.into());
+        self
+    }
+
+    /// Sets how long a request to the upstream may take in total, after which the
+    /// lookup fails. Defaults to 2 seconds.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.agent = agent(timeout);
+        self
+    }
+
+    /// Sets how long links found upstream are cached. Defaults to 60 seconds; zero
+    /// disables caching them, and [`Duration::MAX`] never expires them.
+    #[must_use]
+    pub fn hit_ttl(mut self, ttl: Duration) -> Self {
+        self.hit_ttl = ttl;
+        self
+    }
+
+    /// Sets how long shortlinks the upstream doesn't have are cached. Defaults to 10
+    /// seconds; zero disables caching them, and [`Duration::MAX`] never expires them.
+    #[must_use]
+    pub fn miss_ttl(mut self, ttl: Duration) -> Self {
+        self.miss_ttl = ttl;
+        self
+    }
+
+    /// Sets how many lookups are cached; when full, the least recently used one is
+    /// evicted. Defaults to 1024; zero disables caching.
+    #[must_use]
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache = Arc::new(LinkCache::new(capacity));
+        self
+    }
+
+    /// The base URL of the upstream go link service.
+    #[must_use]
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Forgets every cached lookup, e.g. after the upstream's links were edited.
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    /// The upstream URL of `shortlink`, keeping the `/` between the segments of
+    /// hierarchical shortlinks.
+    fn shortlink_url(&self, shortlink: &str) -> String {
+        let path = shortlink
+            .split('/')
+            .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        format!("{}/{path}", self.base_url)
+    }
+
+    /// The key a lookup of `shortlink` for `context` is cached under.
+    fn key(&self, shortlink: &str, context: &RequestContext) -> CacheKey {
+        CacheKey {
+            shortlink: shortlink.to_string(),
+            user: context
+                .user
+                .as_ref()
+                .filter(|_| self.user_header.is_some())
+                .map(|user| user.login.clone()),
+        }
+    }
+
+    /// Looks `key` up upstream and caches the result.
+    fn fetch_and_cache(&self, key: CacheKey) -> Result<Option<Link>, HttpStoreError> {
+        let link = self.fetch(&key.shortlink, key.user.as_deref())?;
+        let ttl = if link.is_some() {
+            self.hit_ttl
+        } else {
+            self.miss_ttl
+        };
+        self.cache.insert(key, link.clone(), ttl);
+        Ok(link)
+    }
+
+    fn fetch(&self, shortlink: &str, user: Option<&str>) -> Result<Option<Link>, HttpStoreError> {
+        let request_failed = |source| HttpStoreError::Request {
+            instance: self.base_url.clone(),
+            source,
+        };
+
+        let mut request = self
+            .agent
+            .get(format!(
+                "{}{}",
+                self.shortlink_url(shortlink),
+                self.metadata_suffix
+            ))
+            .header("Accept", "application/json");
+        if let Some(name) = &self.user_header
+            && let Some(user) = user
+        {
+            request = request.header(name, user);
+        }
+        let mut response = request.call().map_err(request_failed)?;
+        match response.status() {
+            StatusCode::OK => {
+                let link: Link = response.body_mut().read_json().map_err(request_failed)?;
+                Ok(Some(self.adopt(link)))
+            }
+            StatusCode::NOT_FOUND
+            | StatusCode::GONE
+            | StatusCode::UNAUTHORIZED
+            | StatusCode::FORBIDDEN => Ok(None),
+            status => Err(HttpStoreError::Status {
+                instance: self.base_url.clone(),
+                status: status.as_u16(),
+            }),
+        }
+    }
+
+    /// Adapts a link served by the upstream for resolving here.
+    fn adopt(&self, mut link: Link) -> Link {
+        // An upstream alias names one of the upstream's links, not one of ours
+        if let Some(target) = link.alias_target() {
+            link.url = self.shortlink_url(target);
+            link.kind = LinkKind::Literal;
+            link.signature = None;
+        }
+        let source = link.source.take().unwrap_or_else(|| self.base_url.clone());
+        link.with_source(source)
+    }
+}
+
+impl LinkStore for HttpStore {
+    type Error = HttpStoreError;
+
+    fn lookup(&self, shortlink: &str) -> Result<Option<Link>, HttpStoreError> {
+        LinkStore::lookup_with_context(self, shortlink, &RequestContext::default())
+    }
+
+    fn lookup_with_context(
+        &self,
+        shortlink: &str,
+        context: &RequestContext,
+    ) -> Result<Option<Link>, HttpStoreError> {
+        let key = self.key(shortlink, context);
+        if let Some(cached) = self.cache.get(&key) {
+            return Ok(cached);
+        }
+        self.fetch_and_cache(key)
+    }
+}
+
+impl AsyncLinkStore for HttpStore {
+    type Error = HttpStoreError;
+
+    async fn lookup(&self, shortlink: &str) -> Result<Option<Link>, HttpStoreError> {
+        AsyncLinkStore::lookup_with_context(self, shortlink, &RequestContext::default()).await
+    }
+
+    async fn lookup_with_context(
+        &self,
+        shortlink: &str,
+        context: &RequestContext,
+    ) -> Result<Option<Link>, HttpStoreError> {
+        let key = self.key(shortlink, context);
+        if let Some(cached) = self.cache.get(&key) {
+            return Ok(cached);
+        }
+        let store = self.clone();
+        on_thread(move || store.fetch_and_cache(key)).await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    shortlink: String,
+    /// The requesting user's login, if it's sent upstream.
+    user: Option<String>,
+}
+
+/// An error looking a link up on an upstream go link service, returned by
+/// [`HttpStore`].
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum HttpStoreError {
+    /// The request couldn't be made, timed out, or its response couldn't be read as a
+    /// [`Link`].
+    #[error("lookup on {instance} failed: {source}")]
+    Request {
+        /// The base URL of the upstream.
+        instance: String,
+        /// What went wrong.
+        #[source]
+        source: ureq::Error,
+    },
+
+    /// The upstream answered with a status other than 200 OK or 404 Not Found.
+    #[error("{instance} responded with HTTP {status}")]
+    Status {
+        /// The base URL of the upstream.
+        instance: String,
+        /// The HTTP status code of the response.
+        status: u16,
+    },
+}
+
+fn agent(timeout: Duration) -> Agent {
+    Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+/// Runs `f` on a thread of its own, returning a future of its result, so blocking
+/// requests don't hold up an async executor.
+fn on_thread<T, F>(f: F) -> OnThread<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let state = Arc::new(Mutex::new(ThreadState {
+        result: None,
+        waker: None,
+    }));
+    let finished = Arc::clone(&state);
+    thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        let mut state = finished.lock().unwrap_or_else(PoisonError::into_inner);
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+    OnThread(state)
+}
+
+/// The result of a function run by [`on_thread`].
+struct OnThread<T>(Arc<Mutex<ThreadState<T>>>);
+
+struct ThreadState<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+impl<T> Future for OnThread<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        match state.result.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Federated, GolinkError, GolinkResolution, Resolver, User};
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// The header the tests forward the requesting user in.
+    const USER_HEADER: &str = "X-Forwarded-User";
+
+    /// Serves `respond(path, user)` on a local port, where `user` is the value of the
+    /// [`USER_HEADER`], returning the server's base URL and a count of the requests it
+    /// has received. Each request is served on its own thread.
+    fn serve(respond: fn(&str, Option<&str>) -> (u16, String)) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(&stream);
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).unwrap();
+                    let mut user = None;
+                    let mut header = String::new();
+                    while reader.read_line(&mut header).unwrap() > 2 {
+                        if let Some((name, value)) = header.split_once(':')
+                            && name.eq_ignore_ascii_case(USER_HEADER)
+                        {
+                            user = Some(value.trim().to_string());
+                        }
+                        header.clear();
+                    }
+                    counter.fetch_add(1, Ordering::SeqCst);
+
+                    let path = request_line.split(' ').nth(1).unwrap_or_default();
+                    let (status, body) = respond(path, user.as_deref());
+                    let response = format!(
+                        "HTTP/1.1 {status} Status\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    stream.write_all(response.as_bytes()).unwrap();
+                });
+            }
+        });
+        (base_url, requests)
+    }
+
+    fn upstream(path: &str, user: Option<&str>) -> (u16, String) {
+        match path {
+            "/standup+" => (
+                200,
+                r#"{"url":"https://meet.example.com/all-hands"}"#.to_string(),
+            ),
+            "/team/oncall+" => (200, r#"{"url":"https://oncall.example.com/"}"#.to_string()),
+            "/wiki+" => (
+                200,
+                r#"{"url":"https://wiki.example.com/","source":"https://go.corp.example.com"}"#
+                    .to_string(),
+            ),
+            "/admin+" => (200, r#"{"url":"alias:team/admin"}"#.to_string()),
+            "/salaries+" if user == Some("alice") => (
+                200,
+                r#"{"url":"https://hr.example.com/salaries"}"#.to_string(),
+            ),
+            "/salaries+" => (403, String::new()),
+            "/broken+" => (500, String::new()),
+            "/garbled+" => (200, "<html>".to_string()),
+            _ => (404, String::new()),
+        }
+    }
+
+    fn lookup(store: &HttpStore, shortlink: &str) -> Result<Option<Link>, HttpStoreError> {
+        LinkStore::lookup(store, shortlink)
+    }
+
+    #[test]
+    fn it_looks_links_up_upstream() {
+        let (base_url, _) = serve(upstream);
+        let store = HttpStore::new(format!("{base_url}/"));
+        assert_eq!(store.base_url(), base_url);
+
+        assert_eq!(
+            lookup(&store, "standup").unwrap(),
+            Some(Link::new("https://meet.example.com/all-hands").with_source(&base_url))
+        );
+        assert_eq!(
+            lookup(&store, "team/oncall").unwrap(),
+            Some(Link::new("https://oncall.example.com/").with_source(&base_url))
+        );
+        // Links the upstream got from its own upstream keep their source
+        assert_eq!(
+            lookup(&store, "wiki").unwrap(),
+            Some(Link::new("https://wiki.example.com/").with_source("https://go.corp.example.com"))
+        );
+        assert_eq!(lookup(&store, "missing").unwrap(), None);
+    }
+
+    #[test]
+    fn it_points_upstream_aliases_at_the_upstream() {
+        let (base_url, _) = serve(upstream);
+        let store = HttpStore::new(&base_url);
+        assert_eq!(
+            lookup(&store, "admin").unwrap(),
+            Some(
+                Link::new(format!("{base_url}/team/admin"))
+                    .with_kind(LinkKind::Literal)
+                    .with_source(&base_url)
+            )
+        );
+
+        // Even when a local link has the alias target's name
+        let local = HashMap::from([("team/admin".to_string(), "https://admin.example.com/")]);
+        let store = Federated::new(local, store);
+        let resolver = Resolver::builder().max_shortlink_segments(2).build();
+        let Ok(GolinkResolution::RedirectRequest { url, .. }) =
+            resolver.resolve("/admin/x", &store)
+        else {
+            panic!("expected a redirect");
+        };
+        assert_eq!(url, format!("{base_url}/team/admin/x"));
+    }
+
+    #[test]
+    fn it_forwards_the_requesting_user_when_asked() {
+        let (base_url, requests) = serve(upstream);
+        let alice = RequestContext::new().with_user(User::new("alice"));
+        let bob = RequestContext::new().with_user(User::new("bob"));
+
+        // Links the upstream won't show are reported as not found
+        let anonymous = HttpStore::new(&base_url);
+        assert_eq!(
+            LinkStore::lookup_with_context(&anonymous, "salaries", &alice).unwrap(),
+            None
+        );
+
+        let store = HttpStore::new(&base_url).user_header(USER_HEADER);
+        let salaries = Some(Link::new("https://hr.example.com/salaries").with_source(&base_url));
+        assert_eq!(
+            LinkStore::lookup_with_context(&store, "salaries", &alice).unwrap(),
+            salaries
+        );
+        assert_eq!(
+            LinkStore::lookup_with_context(&store, "salaries", &bob).unwrap(),
+            None
+        );
+        // Each user's lookups are cached separately
+        assert_eq!(
+            LinkStore::lookup_with_context(&store, "salaries", &alice).unwrap(),
+            salaries
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        let resolver = Resolver::default();
+        assert!(matches!(
+            resolver.resolve_with_context("/salaries", &bob, &store),
+            Err(GolinkError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn it_caches_hits_and_misses() {
+        let (base_url, requests) = serve(upstream);
+        let store = HttpStore::new(base_url);
+
+        for _ in 0..3 {
+            assert!(lookup(&store, "standup").unwrap().is_some());
+            assert!(lookup(&store, "missing").unwrap().is_none());
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        store.clear_cache();
+        lookup(&store, "standup").unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        let uncached = store.clone().cache_capacity(0);
+        lookup(&uncached, "standup").unwrap();
+        lookup(&uncached, "standup").unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 5);
+
+        let expiring = store.clone().cache_capacity(8).miss_ttl(Duration::ZERO);
+        lookup(&expiring, "missing").unwrap();
+        lookup(&expiring, "missing").unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 7);
+    }
+
+    #[test]
+    fn it_reports_upstream_failures() {
+        let (base_url, requests) = serve(upstream);
+        let store = HttpStore::new(&base_url);
+
+        assert!(matches!(
+            lookup(&store, "broken"),
+            Err(HttpStoreError::Status { status: 500, .. })
+        ));
+        assert!(matches!(
+            lookup(&store, "garbled"),
+            Err(HttpStoreError::Request { .. })
+        ));
+        // Failures aren't cached
+        assert!(lookup(&store, "broken").is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn it_times_out_slow_upstreams() {
+        // Connections to a listener that never accepts them are never answered
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let store = HttpStore::new(format!("http://{}", listener.local_addr().unwrap()))
+            .timeout(Duration::from_millis(10));
+        assert!(matches!(
+            lookup(&store, "standup"),
+            Err(HttpStoreError::Request {
+                source: ureq::Error::Timeout(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn it_federates_with_a_local_store() {
+        let (base_url, _) = serve(upstream);
+        let local = HashMap::from([("standup".to_string(), "https://meet.example.com/team")]);
+        let store = Federated::new(local, HttpStore::new(&base_url));
+        let resolver = Resolver::builder().max_shortlink_segments(2).build();
+
+        let Ok(GolinkResolution::RedirectRequest { url, link, .. }) =
+            resolver.resolve("/standup", &store)
+        else {
+            panic!("expected a redirect");
+        };
+        assert_eq!(url, "https://meet.example.com/team");
+        assert_eq!(link.source, None);
+
+        let Ok(GolinkResolution::RedirectRequest { url, link, .. }) =
+            resolver.resolve("/Team/On-Call", &store)
+        else {
+            panic!("expected a redirect");
+        };
+        assert_eq!(url, "https://oncall.example.com/");
+        assert_eq!(link.source, Some(base_url));
+
+        assert!(matches!(
+            resolver.resolve("/missing", &store),
+            Err(GolinkError::NotFound { .. })
+        ));
+        assert!(matches!(
+            resolver.resolve("/broken", &store),
+            Err(GolinkError::LookupFailed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn async_it_federates_with_a_local_store() {
+        let (base_url, requests) = serve(upstream);
+        let local = HashMap::from([("standup".to_string(), "https://meet.example.com/team")]);
+        let store = Federated::new(local, HttpStore::new(&base_url));
+        let resolver = Resolver::default();
+
+        for _ in 0..2 {
+            let Ok(GolinkResolution::RedirectRequest { url, link, .. }) =
+                resolver.resolve_async("/wiki", &store).await
+            else {
+                panic!("expected a redirect");
+            };
+            assert_eq!(url, "https://wiki.example.com/");
+            assert_eq!(link.source.as_deref(), Some("https://go.corp.example.com"));
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        assert!(matches!(
+            resolver.resolve_async("/broken", &store).await,
+            Err(GolinkError::LookupFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! Long URL templates in [MiniJinja](https://docs.rs/minijinja) syntax, for
+//! [`TemplateEngine::Jinja`](crate::TemplateEngine::Jinja).
+
+use minijinja::Environment;
+use minijinja::value::{Serde, Value};
+
+use crate::expand::{path_escape, query_escape};
+use crate::{CompiledTemplate, ExpandEnvironment, GolinkError, LinkKind};
+
+const TEMPLATE_NAME: &str = "url_input";
+
+/// Returns whether a long URL of the given kind is rendered as a Jinja template.
+pub(crate) fn is_template(url: &str, kind: LinkKind) -> bool {
+    kind == LinkKind::Template || url.contains("{{") || url.contains("{%")
+}
+
+/// A long URL compiled as a Jinja template.
+#[derive(Debug)]
+pub(crate) struct JinjaUrl {
+    environment: Environment<'static>,
+
+    /// The variables the template can use.
+    variables: Vec<String>,
+}
+
+impl JinjaUrl {
+    pub(crate) fn compile(url: &str, variables: &[&str]) -> Result<Self, GolinkError> {
+        let mut environment = Environment::new();
+        environment.add_filter("path_escape", |value: &str| path_escape(value));
+        environment.add_filter("query_escape", |value: &str| query_escape(value));
+        environment.add_template_owned(TEMPLATE_NAME, url.to_string())?;
+        Ok(Self {
+            environment,
+            variables: variables.iter().map(|name| (*name).to_string()).collect(),
+        })
+    }
+}
+
+impl CompiledTemplate for JinjaUrl {
+    fn render(&self, environment: &ExpandEnvironment) -> Result<String, GolinkError> {
+        let template = self.environment.get_template(TEMPLATE_NAME)?;
+        Ok(template.render(Value::from(Serde(environment)))?)
+    }
+
+    /// Checks for variables that are neither template values nor Jinja globals.
+    fn validate(&self) -> Result<(), GolinkError> {
+        let template = self.environment.get_template(TEMPLATE_NAME)?;
+        let mut unknown = template
+            .undeclared_variables(false)
+            .into_iter()
+            .filter(|name| {
+                !self.variables.contains(name)
+                    && !self.environment.globals().any(|(global, _)| global == name)
+            })
+            .collect::<Vec<_>>();
+        unknown.sort();
+
+        match unknown.first() {
+            Some(name) => Err(GolinkError::TemplateError(format!(
+                "Unknown template variable '{name}'"
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TemplateEngine;
+    use crate::expand::CompiledUrl;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str, remainder: &[&str]) -> Result<String, GolinkError> {
+        let remainder = remainder
+            .iter()
+            .map(|s| (*s).to_string())
+            .collect::<Vec<_>>();
+        let url = CompiledUrl::compile(template, LinkKind::Auto, None, &TemplateEngine::Jinja)?;
+        let environment = url.environment(&remainder)?.with_query(Some("tab=files"));
+        url.expand(&environment, crate::PathAppend::Append)
+    }
+
+    #[test]
+    fn it_renders_jinja_templates() {
+        let template =
+            "https://example.com/{% if path %}search?q={{ path | query_escape }}{% endif %}";
+        assert_eq!(
+            render(template, &[]),
+            Ok("https://example.com/".to_string())
+        );
+        assert_eq!(
+            render(template, &["a b"]),
+            Ok("https://example.com/search?q=a%20b".to_string())
+        );
+        assert_eq!(
+            render(
+                "https://example.com/{{ segments[1] }}/{{ segment_count }}?tab={{ query.tab }}",
+                &["a", "b"]
+            ),
+            Ok("https://example.com/b/2?tab=files".to_string())
+        );
+        assert_eq!(
+            render("https://example.com/{ path }", &["a"]),
+            Ok("https://example.com/%7B%20path%20%7D/a".to_string())
+        );
+    }
+
+    #[test]
+    fn it_validates_jinja_templates() {
+        let validate = |template: &str| {
+            let url = CompiledUrl::compile(template, LinkKind::Auto, None, &TemplateEngine::Jinja)?;
+            url.validate()
+        };
+        assert_eq!(validate("https://example.com/{{ path }}"), Ok(()));
+        assert_eq!(
+            validate("https://example.com/{% for s in segments %}{{ s }}{% endfor %}"),
+            Ok(())
+        );
+        assert_eq!(
+            validate("https://example.com/{{ pth }}"),
+            Err(GolinkError::TemplateError(
+                "Unknown template variable 'pth'".to_string()
+            ))
+        );
+        assert!(matches!(
+            validate("https://example.com/{% if path %}"),
+            Err(GolinkError::TemplateError(_))
+        ));
+    }
+}
@@ -4,8 +4,9 @@
 //! You provide a link to expand and a function for mapping short URLs to long URLs,
 //! and this crate will:
 //!
-//! - **Normalize your input to ignore case and hyphenation**: `http://go/My-Service`
-//!   and `http://go/myservice` are treated as the same input into your mapping function
+//! - **Normalize your input to ignore case, hyphenation and Unicode variants**:
+//!   `http://go/My-Service` and `http://go/myservice` are treated as the same input into
+//!   your mapping function
 //!
 //! - **Append secondary paths to your resolved URL**: if your mapping function returns
 //!   `http://example.com` for the given shortlink `foo`, then a request to `http://go/foo/bar/baz`
@@ -35,8 +36,64 @@
 //!   https://github.com/pulls?q=is:open+is:pr+review-requested:jameslittle230+archived:false
 //!   ```
 //!
+//!   Template values are percent-escaped for where they appear: `{ path }` in the path of
+//!   the long URL keeps `/` separators, and after a `?` or `#` it is escaped as a query
+//!   value. Use `{ path | path_escape }`, `{ path | query_escape }` or
+//!   `{ path | unescaped }` to choose explicitly.
+//!
+//!   The remaining path is percent-decoded for `{ path }`; `{ raw_path }` has it exactly
+//!   as requested, so an escaped `%2F` can be told apart from a `/`.
+//!
+//!   Individual segments are available as `{ segments.0 }` (or `{ segments[0] }`), along
+//!   with `{ segment_count }`. A link can also declare a [signature](Link::signature)
+//!   such as `gh/{org}/{repo}`, whose named parameters bind to the remaining segments
+//!   in order, so its long URL can read `https://github.com/{ org }/{ repo }`.
+//!   Requests missing a required parameter fail with `GolinkError::MissingParameters`.
+//!
+//!   A long URL is only treated as a template if it uses template syntax, so literal
+//!   braces, like a JSON filter in a dashboard URL, pass through untouched. Mark a link
+//!   [`LinkKind::Literal`] to never render it, write `\{` for a literal brace inside a
+//!   template, and check links with [`validate_link()`] before storing them.
+//!
+//! - **Follow aliases**: a long value written as `go/other` or `alias:other` makes the
+//!   resolver look up `other` instead, carrying over any remaining path segments, so
+//!   links can be renamed without breaking their old names.
+//!
 //! This resolver performs all the functionality described in [Tailscale's Golink
-//! project](https://tailscale.com/blog/golink/)
+//! project](https://tailscale.com/blog/golink/). Links exported from a Tailscale golink
+//! instance use Go template syntax like `{{if .Path}}{{QueryEscape .Path}}{{end}}`;
+//! select [`TemplateEngine::GoTemplate`] to resolve them the same way, and
+//! [`QueryPassthrough::Append`] to pass query strings through as Tailscale does.
+//!
+//! Template engines are pluggable: every [`TemplateEngine`] is an [`Expander`], and
+//! [`ResolverBuilder::expander`] accepts your own implementation, so links written for
+//! another link shortener can keep their format. The `go-template` feature (on by
+//! default) provides [`TemplateEngine::GoTemplate`]; the `minijinja` feature adds
+//! `TemplateEngine::Jinja` for Jinja-style templates.
+//!
+//! To make links user-aware, resolve with a [`RequestContext`] naming the requesting
+//! [`User`] via [`Resolver::resolve_with_context`]. Templates can then read
+//! `{ user.login }` (or `{{.User}}` in Go templates), so `go/me` can expand to the
+//! caller's profile page, and stores receive the context through
+//! [`LinkStore::lookup_with_context`].
+//!
+//! Enable [`.layered_links(true)`](ResolverBuilder::layered_links) to give users their
+//! own links: `go/standup` then resolves to the user's personal link (stored as
+//! `~alice/standup`), else their group's (`@eng/standup`), else the global one, and
+//! `go/~alice/notes` reaches one of alice's links directly (for alice only: other users'
+//! personal links and other groups' links are forbidden). Every resolution reports the
+//! [`LinkLayer`] it matched, for analytics and metadata pages.
+//!
+//! Links can also restrict who resolves them: set a link's [`Access`] to
+//! `Authenticated`, or to specific users and groups, and requests from anyone else fail
+//! with [`GolinkError::Forbidden`] (HTTP 403), which names the shortlink but never the
+//! long URL.
+//!
+//! Expanded URLs are checked before they're returned: by default only `http`, `https`
+//! and relative targets without control characters are allowed, so a careless or
+//! malicious link edit can't redirect to `javascript:` URLs or inject headers. Anything
+//! else fails with [`GolinkError::UnsafeTarget`]; configure the checks, including host
+//! allow and deny lists, with [`ResolverBuilder::safety_policy`].
 //!
 //! This crate doesn't provide a web service or an interface for creating shortened links;
 //! it only provides an algorithm for resolving short URLs to long URLs.
@@ -57,19 +114,24 @@
 //!     None
 //! }
 //!
-//! let resolved = golink::resolve("/foo", &lookup);
-//!  //         or golink::resolve("foo", &lookup);
-//!  //         or golink::resolve("https://example.com/foo", &lookup);
+//! let resolved = golink::resolve("/foo", lookup);
+//!  //         or golink::resolve("foo", lookup);
+//!  //         or golink::resolve("https://example.com/foo", lookup);
 //!
 //! match resolved {
-//!    Ok(golink::GolinkResolution::RedirectRequest { url, shortlink }) => {
-//!        // Redirect to `url`
+//!    Ok(golink::GolinkResolution::RedirectRequest { url, shortlink, link, .. }) => {
+//!        // Redirect to `url` with `link.redirect_kind.status_code()`
 //!        // If you collect analytics, then increment the click count for `shortlink`
 //!    }
 //!
-//!    Ok(golink::GolinkResolution::MetadataRequest(key)) => {
-//!        // `key` is the original shortlink.
-//!        // Return JSON that displays metadata/analytics about `key`
+//!    Ok(golink::GolinkResolution::MetadataRequest { shortlink, link, .. }) => {
+//!        // `shortlink` is the normalized shortlink and `link` is the stored link.
+//!        // Return JSON that displays metadata/analytics about `shortlink`
+//!    }
+//!
+//!    Ok(golink::GolinkResolution::Fallback { url, shortlink }) => {
+//!        // Only with a fallback policy: `shortlink` wasn't found, so redirect to `url`
+//!        // (e.g. a search page) and count a miss
 //!    }
 //!
 //!    Err(e) => {
@@ -80,7 +142,10 @@
 //!
 //! ### Asynchronous API
 //!
-//! For async contexts (e.g., database lookups), use `resolve_async()`:
+//! For async contexts (e.g., database lookups), use `resolve_async()`. Lookups may return
+//! a long URL `String` or a full [`Link`] with metadata. If your lookup can fail, return
+//! `Result<Option<_>, E>` and failures will be reported as
+//! [`GolinkError::LookupFailed`] instead of [`GolinkError::NotFound`]:
 //!
 //! ```rust
 //! # async fn example() {
@@ -96,12 +161,15 @@
 //! }).await;
 //!
 //! match resolved {
-//!    Ok(golink::GolinkResolution::RedirectRequest { url, shortlink }) => {
+//!    Ok(golink::GolinkResolution::RedirectRequest { url, shortlink, .. }) => {
 //!        // Redirect to `url`
 //!        // Optionally use `shortlink` for analytics
 //!    }
-//!    Ok(golink::GolinkResolution::MetadataRequest(key)) => {
-//!        // Return metadata about `key`
+//!    Ok(golink::GolinkResolution::MetadataRequest { shortlink, link, .. }) => {
+//!        // Return metadata about `shortlink` from `link`
+//!    }
+//!    Ok(golink::GolinkResolution::Fallback { url, .. }) => {
+//!        // Redirect to `url`
 //!    }
 //!    Err(e) => {
 //!        // Handle error
@@ -109,145 +177,235 @@
 //! }
 //! # }
 //! ```
+//!
+//! ### Link stores
+//!
+//! A [`Resolver`] reads long URLs from a [`LinkStore`] (or an [`AsyncLinkStore`] in
+//! async contexts). Closures, `HashMap<String, String>` and `BTreeMap<String, String>`
+//! implement both traits, and you can implement them for your own database handle:
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//!
+//! let links = HashMap::from([("foo".to_string(), "http://example.com".to_string())]);
+//! let resolved = golink::Resolver::default().resolve("/foo", &links);
+//! ```
+//!
+//! To federate go link services, wrap your store and another instance's in a [`Federated`]
+//! store: links missing locally are looked up upstream, e.g. on the company-wide
+//! instance with an `HttpStore` from the `http` feature, which caches hits and misses
+//! and times out slow requests. Links found upstream name the instance that answered
+//! in their [`source`](Link::source).
+//!
+//! To avoid querying your database for the same hot links over and over, wrap its
+//! store in a [`CachedStore`]: it keeps recent lookups, including links that weren't
+//! found, in a bounded LRU cache with separate TTLs for hits and misses, and works for
+//! both sync and async resolution. Call [`CachedStore::invalidate`] when a link is created,
+//! edited or deleted so the change takes effect immediately.
+//!
+//! ### Configuring a resolver
+//!
+//! The free functions above use the default policies. To change them, build a
+//! [`Resolver`] once at startup and reuse it for every request:
+//!
+//! ```rust
+//! use golink::{Normalizer, PathAppend, QueryPassthrough, Resolver};
+//!
+//! let resolver = Resolver::builder()
+//!     .normalizer(Normalizer::new().case_sensitive(true))
+//!     .metadata_suffix("+")
+//!     .path_append(PathAppend::Discard)
+//!     .max_input_length(1024)
+//!     .query_passthrough(QueryPassthrough::KeepTarget)
+//!     .build();
+//!
+//! # fn lookup(input: &str) -> Option<String> { None }
+//! let resolved = resolver.resolve("/foo", &lookup);
+//! ```
+//!
+//! The [`Normalizer`] decides which requests name the same shortlink. By default it
+//! applies Unicode NFKC normalization and case folding and removes dashes and
+//! whitespace, so full-width letters, en dashes and non-breaking spaces match what
+//! users think they typed; it can also keep case, remove underscores and dots, or
+//! strip and keep any character class. Store keys with
+//! [`Resolver::normalize_shortlink`] so they follow the same rules as lookups, and use
+//! [`Normalizer::is_confusable`] to turn away lookalike shortlinks like `pаypal` with a
+//! Cyrillic `а`.
+//!
+//! Links pasted from a sentence, like `go/docs).`, still resolve: when a shortlink isn't
+//! found as typed, the resolver retries without its trailing punctuation and stray
+//! brackets, and reports what it removed in the resolution's `trimmed` field. The same
+//! punctuation after a link's parameters, as in `go/docs/api).`, is left out of the
+//! expanded URL. Turn this off with [`.trim_punctuation(false)`](ResolverBuilder::trim_punctuation).
+//!
+//! For a "did you mean?" 404 page, build the resolver with [`.suggestions(3)`](ResolverBuilder::suggestions):
+//! [`GolinkError::NotFound`] then lists stored shortlinks within a few typos of the request, so
+//! `go/standpu` can offer `go/standup`. Suggestions come from
+//! [`LinkStore::shortlinks`], which `HashMap` and `BTreeMap` stores implement; implement it
+//! for your own store, optionally weighting links by popularity. Links the requester may
+//! not resolve, and unlisted links, are never suggested.
+//!
+//! Unknown shortlinks can redirect instead of failing: pass
+//! [`.fallback()`](ResolverBuilder::fallback) a [`FallbackPolicy`] to send them to a
+//! search page, to a page for creating the link, or to another go link service that
+//! might have it. These resolve to [`GolinkResolution::Fallback`], so misses still
+//! show up in analytics.
+//!
+//! Hierarchical shortlinks like `go/team/oncall` are opt-in: with
+//! [`.max_shortlink_segments(2)`](ResolverBuilder::max_shortlink_segments), the resolver looks up `team/oncall` before `team`, and
+//! whatever follows the longest match becomes the remaining path. Normalize stored keys
+//! for these links with [`normalize_shortlink_path()`].
+//!
+//! Query strings are dropped by default, though templates can always read them as
+//! `{ query.q }`. Pass [`.query_passthrough()`](ResolverBuilder::query_passthrough) a
+//! [`QueryPassthrough`] to merge the request's parameters into the long URL instead,
+//! choosing whether the long URL or the request wins when both set the same parameter,
+//! and enable [`.fragment_passthrough(true)`](ResolverBuilder::fragment_passthrough) to
+//! carry `go/docs#install` over to the target.
+//!
+//! A resolver parses each long URL once and caches the result as a [`CompiledLink`],
+//! so hot links skip template parsing on later requests. Size the cache with
+//! [`.compiled_link_capacity()`](ResolverBuilder::compiled_link_capacity), and share one
+//! resolver (or its clones) across request handlers so they share the cache.
 
-use itertools::Itertools;
-use serde::Serialize;
-use thiserror::Error;
-use tinytemplate::TinyTemplate;
-use url::{ParseError, Url};
-
-#[derive(Debug, Serialize)]
-struct ExpandEnvironment {
-    path: String,
-}
-
-fn expand(input: &str, environment: &ExpandEnvironment) -> Result<String, GolinkError> {
-    let mut tt = TinyTemplate::new();
-    tt.add_template("url_input", input)?;
-    let rendered = tt.render("url_input", environment)?;
-
-    // If rendering didn't result in a different output, assume there is no render
-    // syntax in our long value and instead append the incoming remainder path onto the
-    // expanded URL's path
-    if input == rendered {
-        if let Ok(mut url) = Url::parse(input) {
-            if !environment.path.is_empty() {
-                let base_path = url.path().trim_end_matches('/');
-                url.set_path(&format!("{base_path}/{}", environment.path));
-            }
-            Ok(url.to_string())
-        } else if environment.path.is_empty() {
-            Ok(rendered)
-        } else {
-            Ok(format!("{rendered}/{}", environment.path))
-        }
-    } else {
-        Ok(rendered)
-    }
-}
-
-/// Errors that can occur during shortlink resolution.
-///
-/// These errors are designed to map naturally to HTTP status codes:
-/// - `InvalidInput` → HTTP 400 Bad Request
-/// - `NotFound` → HTTP 404 Not Found
-/// - `TemplateError` → HTTP 500 Internal Server Error
-///
-/// # Example: Mapping to HTTP Status Codes
-///
-/// ```
-/// use golink::{resolve, GolinkError};
-///
-/// fn lookup(key: &str) -> Option<String> {
-///     // Your lookup implementation
-/// #   None
-/// }
-///
-/// fn handle_request(path: &str) -> (u16, String) {
-///     match resolve(path, lookup) {
-///         Ok(resolution) => {
-///             // Handle successful resolution
-/// #           (200, "OK".to_string())
-///         }
-///         Err(GolinkError::InvalidInput) => {
-///             (400, format!("Invalid shortlink {path}"))
-///         }
-///         Err(GolinkError::NotFound(shortlink)) => {
-///             (404, format!("Shortlink '{shortlink}' not found"))
-///         }
-///         Err(GolinkError::TemplateError(msg)) => {
-///             // Log this error - it indicates a data integrity problem
-///             eprintln!("Template error: {msg}");
-///             (500, "Internal Server Error".to_string())
-///         }
-///     }
-/// }
-/// ```
-#[derive(Error, Debug, Clone, PartialEq, Eq)]
-pub enum GolinkError {
-    /// The input shortlink is invalid or malformed.
-    ///
-    /// This covers cases like:
-    /// - Strings that don't make sense as a URL path (e.g. `a:b`)
-    /// - Empty input, or empty input after normalization
-    ///
-    /// **Recommended HTTP status: 400 Bad Request**
-    #[error("Invalid input")]
-    InvalidInput,
-
-    /// The shortlink was not found in the lookup function.
-    ///
-    /// The lookup function returned `None` for the given shortlink.
-    /// The contained `String` is the normalized shortlink that was not found.
-    ///
-    /// **Recommended HTTP status: 404 Not Found**
-    #[error("Shortlink '{0}' not found")]
-    NotFound(String),
-
-    /// The long URL contains invalid template syntax.
-    ///
-    /// This indicates a configuration or data integrity problem - the stored long URL
-    /// has malformed template syntax. This is not the user's fault.
-    ///
-    /// **Recommended HTTP status: 500 Internal Server Error**
-    #[error("Template error: {0}")]
-    TemplateError(String),
-}
-
-impl From<ParseError> for GolinkError {
-    fn from(_: ParseError) -> Self {
-        GolinkError::InvalidInput
-    }
-}
+mod cache;
+mod compiled;
+mod context;
+mod error;
+mod expand;
+mod expander;
+mod fallback;
+mod federated;
+#[cfg(feature = "go-template")]
+mod gotemplate;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "minijinja")]
+mod jinja;
+mod link;
+mod normalize;
+mod resolver;
+mod safety;
+mod store;
+mod suggest;
 
-impl From<tinytemplate::error::Error> for GolinkError {
-    fn from(tt_error: tinytemplate::error::Error) -> Self {
-        GolinkError::TemplateError(tt_error.to_string())
-    }
-}
+pub use cache::CachedStore;
+pub use compiled::CompiledLink;
+pub use context::{RequestContext, User};
+pub use error::{GolinkError, LookupError};
+pub use expand::{ExpandEnvironment, PathAppend, QueryPassthrough, TemplateEngine};
+pub use expander::{CompiledTemplate, Expander};
+pub use fallback::FallbackPolicy;
+pub use federated::{Federated, FederatedError};
+#[cfg(feature = "http")]
+pub use http::{HttpStore, HttpStoreError};
+pub use link::{Access, Link, LinkKind, LinkLayer, RedirectKind, Visibility};
+pub use normalize::{CharClass, Normalizer};
+pub use resolver::{Resolver, ResolverBuilder};
+pub use safety::SafetyPolicy;
+pub use store::{AsyncLinkStore, IntoLookupResult, LinkStore};
+pub use suggest::Candidate;
 
 /// The result of resolving a short URL.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GolinkResolution {
     /// A request for metadata about a shortlink (triggered by a trailing '+').
     ///
-    /// The contained `String` is the normalized shortlink that metadata was requested for.
     /// You should return analytics, usage statistics, or other metadata about this shortlink.
-    MetadataRequest(String),
+    MetadataRequest {
+        /// The normalized shortlink that metadata was requested for.
+        shortlink: String,
+        /// The stored link, including its owner, description and other metadata.
+        link: Link,
+        /// The layer the link was found in.
+        layer: LinkLayer,
+        /// The trailing punctuation the resolver removed from the request to find the
+        /// link, or `None` if the request matched as typed.
+        trimmed: Option<String>,
+    },
 
     /// A request to redirect to the expanded URL.
     ///
-    /// Contains the expanded URL to redirect to and the normalized shortlink that was used.
+    /// Contains the expanded URL to redirect to, the normalized shortlink that was used,
+    /// and the stored link it was expanded from.
     RedirectRequest {
         /// The fully expanded URL to redirect the user to.
         url: String,
         /// The normalized shortlink that was resolved.
         shortlink: String,
+        /// The stored link that `url` was expanded from. Use its
+        /// [`redirect_kind`](Link::redirect_kind) to pick the HTTP status code.
+        ///
+        /// If `shortlink` is an [alias](Link::alias_target), this is the link at the
+        /// end of the alias chain.
+        link: Link,
+        /// The layer `link` was found in: the requesting user's personal links, one of
+        /// their groups' links, or the global links.
+        layer: LinkLayer,
+        /// The trailing punctuation the resolver removed from the request to find the
+        /// link, like the `).` of a pasted `go/docs).`, or `None` if the request matched
+        /// as typed. See [`ResolverBuilder::trim_punctuation`].
+        trimmed: Option<String>,
+    },
+
+    /// A request for a shortlink that isn't stored, redirected by the resolver's
+    /// [fallback policy](ResolverBuilder::fallback) to a search page, a page for
+    /// creating the link, or another go link service.
+    ///
+    /// Redirect to `url` as you would for a `RedirectRequest`, but count it as a miss.
+    Fallback {
+        /// The URL to redirect the user to.
+        url: String,
+        /// The normalized shortlink that wasn't found.
+        shortlink: String,
     },
 }
 
-/// Normalizes a shortlink by extracting the first path segment and converting to lowercase,
-/// removing hyphens and spaces.
+impl GolinkResolution {
+    /// The normalized shortlink that was resolved, or that wasn't found for a
+    /// `Fallback`.
+    #[must_use]
+    pub fn shortlink(&self) -> &str {
+        match self {
+            GolinkResolution::MetadataRequest { shortlink, .. }
+            | GolinkResolution::RedirectRequest { shortlink, .. }
+            | GolinkResolution::Fallback { shortlink, .. } => shortlink,
+        }
+    }
+
+    /// The stored link that was resolved, or `None` for a `Fallback`.
+    #[must_use]
+    pub fn link(&self) -> Option<&Link> {
+        match self {
+            GolinkResolution::MetadataRequest { link, .. }
+            | GolinkResolution::RedirectRequest { link, .. } => Some(link),
+            GolinkResolution::Fallback { .. } => None,
+        }
+    }
+
+    /// The layer the link was found in, or `None` for a `Fallback`.
+    #[must_use]
+    pub fn layer(&self) -> Option<&LinkLayer> {
+        match self {
+            GolinkResolution::MetadataRequest { layer, .. }
+            | GolinkResolution::RedirectRequest { layer, .. } => Some(layer),
+            GolinkResolution::Fallback { .. } => None,
+        }
+    }
+
+    /// The trailing punctuation removed from the request to find the link, if any.
+    #[must_use]
+    pub fn trimmed(&self) -> Option<&str> {
+        match self {
+            GolinkResolution::MetadataRequest { trimmed, .. }
+            | GolinkResolution::RedirectRequest { trimmed, .. } => trimmed.as_deref(),
+            GolinkResolution::Fallback { .. } => None,
+        }
+    }
+}
+
+/// Normalizes a shortlink by extracting the first path segment, applying Unicode
+/// normalization and case folding, and removing dashes and whitespace.
 ///
 /// This function is useful when storing new shortlinks in your database. By normalizing
 /// the user-provided shortlink before saving it, you ensure that lookups are consistent
@@ -259,74 +417,97 @@ pub enum GolinkResolution {
 /// shortlinks that won't match during resolution.
 ///
 /// This applies the same normalization rules used internally by `resolve()` and
-/// `resolve_async()`.
+/// `resolve_async()`, which reject percent-escapes that don't decode to valid UTF-8
+/// rather than guess at them.
+///
+/// # Errors
+///
+/// - `InvalidInput`: The segment's escapes aren't valid UTF-8 (e.g. `%FF`)
 ///
 /// # Examples
 ///
 /// ```
+/// use golink::GolinkError;
+///
 /// // When a user creates a new shortlink, normalize it before storing
 /// let user_input = "My-Service";
-/// let normalized = golink::normalize_shortlink(user_input);
+/// let normalized = golink::normalize_shortlink(user_input)?;
 /// // Store `normalized` ("myservice") in your database (make sure to
 /// // check for duplicates!)
 ///
-/// assert_eq!(golink::normalize_shortlink("My-Service"), "myservice");
-/// assert_eq!(golink::normalize_shortlink("FOO"), "foo");
-/// assert_eq!(golink::normalize_shortlink("my service"), "myservice");
+/// assert_eq!(golink::normalize_shortlink("My-Service")?, "myservice");
+/// assert_eq!(golink::normalize_shortlink("FOO")?, "foo");
+/// assert_eq!(golink::normalize_shortlink("my service")?, "myservice");
 ///
 /// // Extracts only the first path segment
-/// assert_eq!(golink::normalize_shortlink("foo/bar"), "foo");
-/// assert_eq!(golink::normalize_shortlink("/foo/bar/baz"), "foo");
-/// assert_eq!(golink::normalize_shortlink("My-Service/docs"), "myservice");
+/// assert_eq!(golink::normalize_shortlink("foo/bar")?, "foo");
+/// assert_eq!(golink::normalize_shortlink("/foo/bar/baz")?, "foo");
+/// assert_eq!(golink::normalize_shortlink("My-Service/docs")?, "myservice");
+///
+/// // A shortlink that could never be requested can't be stored either
+/// assert_eq!(golink::normalize_shortlink("caf%E9"), Err(GolinkError::InvalidInput));
+/// # Ok::<(), GolinkError>(())
 /// ```
-#[must_use]
-pub fn normalize_shortlink(input: &str) -> String {
-    // Extract first non-empty path segment
-    let first_segment = input
-        .trim_start_matches('/')
-        .split('/')
-        .next()
-        .unwrap_or("");
-
-    normalize_segment(first_segment)
+pub fn normalize_shortlink(input: &str) -> Result<String, GolinkError> {
+    Normalizer::default().normalize_shortlink(input)
 }
 
-/// Normalizes a single shortlink segment (internal helper).
-fn normalize_segment(segment: &str) -> String {
-    segment
-        .to_ascii_lowercase()
-        .replace('-', "")
-        .replace("%20", "")
-        .replace(' ', "")
-}
-
-struct ParsedInput {
-    short: String,
-    remainder: String,
-    is_metadata_request: bool,
+/// Normalizes every path segment of a hierarchical shortlink, joining them with `/`.
+///
+/// This is the multi-segment counterpart of [`normalize_shortlink()`], for resolvers
+/// built with [`max_shortlink_segments`](ResolverBuilder::max_shortlink_segments)
+/// greater than 1. Each segment is normalized with the same rules, stopping at the first
+/// segment that normalizes to nothing, just as the resolver does when building keys.
+///
+/// # Errors
+///
+/// - `InvalidInput`: A segment's escapes aren't valid UTF-8 (e.g. `%FF`)
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(golink::normalize_shortlink_path("Team/On-Call")?, "team/oncall");
+/// assert_eq!(golink::normalize_shortlink_path("/team/on call/")?, "team/oncall");
+/// assert_eq!(golink::normalize_shortlink_path("FOO")?, "foo");
+/// # Ok::<(), golink::GolinkError>(())
+/// ```
+pub fn normalize_shortlink_path(input: &str) -> Result<String, GolinkError> {
+    Normalizer::default().normalize_shortlink_path(input)
 }
 
-fn parse_input(input: &str) -> Result<ParsedInput, GolinkError> {
-    let url = Url::parse(input).or_else(|_| Url::parse("https://go/")?.join(input))?;
-    let mut segments = url.path_segments().ok_or(GolinkError::InvalidInput)?;
-    let short = normalize_segment(segments.next().ok_or(GolinkError::InvalidInput)?);
-
-    if short.is_empty() {
-        return Err(GolinkError::InvalidInput);
-    }
-
-    let is_metadata_request = url.path().ends_with('+');
-    let remainder = segments.join("/");
-
-    Ok(ParsedInput {
-        short,
-        remainder,
-        is_metadata_request,
-    })
+/// Checks a link before it is stored, using the default template engine.
+///
+/// Returns how the long URL will be treated: [`LinkKind::Template`] if it will be
+/// rendered, or [`LinkKind::Literal`] if it will be used as-is, with remaining path
+/// segments appended. A link of kind [`LinkKind::Auto`] is only a template if it uses
+/// template syntax, so literal braces like those in a JSON query parameter are safe:
+///
+/// ```
+/// use golink::{GolinkError, Link, LinkKind};
+///
+/// let dashboard = Link::new(r#"https://grafana.example.com/d?filter={"a":1}"#);
+/// assert_eq!(golink::validate_link(&dashboard), Ok(LinkKind::Literal));
+///
+/// let search = Link::new("https://example.com/search?q={ path }");
+/// assert_eq!(golink::validate_link(&search), Ok(LinkKind::Template));
+///
+/// let typo = Link::new("https://example.com/{ paht }").with_kind(LinkKind::Template);
+/// assert!(matches!(golink::validate_link(&typo), Err(GolinkError::TemplateError(_))));
+/// ```
+///
+/// # Errors
+///
+/// - `TemplateError`: The link's signature is invalid, or its long URL is a template
+///   with invalid syntax, an unknown variable or an unknown formatter
+pub fn validate_link(link: &Link) -> Result<LinkKind, GolinkError> {
+    Resolver::default().validate_link(link)
 }
 
 /// Resolves a short URL to its expanded form using the provided synchronous lookup function.
 ///
+/// The lookup function returns `Option<String>` (or `Option<Link>` to attach metadata),
+/// or `Result<Option<_>, E>` if it can fail; see [`IntoLookupResult`].
+///
 /// # Examples
 ///
 /// ```
@@ -343,7 +524,7 @@ fn parse_input(input: &str) -> Result<ParsedInput, GolinkError> {
 /// // Basic resolution
 /// let result = resolve("/home", lookup).unwrap();
 /// match result {
-///     GolinkResolution::RedirectRequest { url, shortlink } => {
+///     GolinkResolution::RedirectRequest { url, shortlink, .. } => {
 ///         assert_eq!(url, "https://example.com/");
 ///         assert_eq!(shortlink, "home");
 ///     }
@@ -362,8 +543,9 @@ fn parse_input(input: &str) -> Result<ParsedInput, GolinkError> {
 /// // Metadata request (trailing '+')
 /// let result = resolve("/home+", lookup).unwrap();
 /// match result {
-///     GolinkResolution::MetadataRequest(key) => {
-///         assert_eq!(key, "home");
+///     GolinkResolution::MetadataRequest { shortlink, link, .. } => {
+///         assert_eq!(shortlink, "home");
+///         assert_eq!(link.url, "https://example.com/");
 ///     }
 ///     _ => panic!("Expected MetadataRequest"),
 /// }
@@ -371,41 +553,31 @@ fn parse_input(input: &str) -> Result<ParsedInput, GolinkError> {
 ///
 /// # Errors
 ///
-/// - `InvalidInput`: The input URL is malformed, has no path segments, or the shortlink is empty
-/// - `NotFound`: The lookup function returned `None` for the shortlink
-/// - `TemplateError`: The long URL contains invalid template syntax
-pub fn resolve<F>(input: &str, lookup: F) -> Result<GolinkResolution, GolinkError>
+/// - `InvalidInput`: The input URL is malformed, too long, has no path segments, or the shortlink is empty
+/// - `NotFound`: The lookup function or store returned `None` for the shortlink (including
+///   for metadata requests)
+/// - `Forbidden`: The link's access policy doesn't admit the requesting user (here, an
+///   anonymous request)
+/// - `MissingParameters`: The request lacks a required parameter of the link's signature
+/// - `TemplateError`: The long URL contains invalid template syntax, or the link's signature is invalid
+/// - `LookupFailed`: The lookup function or store returned an error
+/// - `AliasCycle`: The link is an alias whose chain loops or exceeds the maximum alias depth
+/// - `BrokenAlias`: The link is an alias whose target isn't stored
+/// - `UnsafeTarget`: The expanded URL breaks the safety policy; by default, it isn't an `http`
+///   or `https` URL or relative path, contains control characters, or is too long
+pub fn resolve<F, R>(input: &str, lookup: F) -> Result<GolinkResolution, GolinkError>
 where
-    F: Fn(&str) -> Option<String>,
+    F: Fn(&str) -> R,
+    R: IntoLookupResult,
 {
-    let parsed = parse_input(input)?;
-
-    if parsed.is_metadata_request {
-        return Ok(GolinkResolution::MetadataRequest(
-            parsed.short.trim_end_matches('+').to_string(),
-        ));
-    }
-
-    let lookup_value =
-        lookup(&parsed.short).ok_or_else(|| GolinkError::NotFound(parsed.short.clone()))?;
-
-    let expansion = expand(
-        &lookup_value,
-        &ExpandEnvironment {
-            path: parsed.remainder,
-        },
-    )?;
-
-    Ok(GolinkResolution::RedirectRequest {
-        url: expansion,
-        shortlink: parsed.short,
-    })
+    Resolver::default().resolve(input, &lookup)
 }
 
 /// Resolves a short URL to its expanded form using the provided asynchronous lookup function.
 ///
 /// This is useful when your shortlink lookup requires async operations like database queries
-/// or HTTP requests.
+/// or HTTP requests. Return `Result<Option<_>, E>` from the future so that a failed
+/// query is reported as [`GolinkError::LookupFailed`] rather than [`GolinkError::NotFound`].
 ///
 /// # Examples
 ///
@@ -426,7 +598,7 @@ where
 /// }).await.unwrap();
 ///
 /// match result {
-///     GolinkResolution::RedirectRequest { url, shortlink } => {
+///     GolinkResolution::RedirectRequest { url, shortlink, .. } => {
 ///         assert_eq!(url, "https://example.com/");
 ///         assert_eq!(shortlink, "home");
 ///     }
@@ -456,37 +628,14 @@ where
 ///
 /// # Errors
 ///
-/// - `InvalidInput`: The input URL is malformed, has no path segments, or the shortlink is empty
-/// - `NotFound`: The lookup function returned `None` for the shortlink
-/// - `TemplateError`: The long URL contains invalid template syntax
+/// See [`resolve()`].
 pub async fn resolve_async<F, Fut>(input: &str, lookup: F) -> Result<GolinkResolution, GolinkError>
 where
     F: Fn(&str) -> Fut,
-    Fut: std::future::Future<Output = Option<String>>,
+    Fut: std::future::Future,
+    Fut::Output: IntoLookupResult,
 {
-    let parsed = parse_input(input)?;
-
-    if parsed.is_metadata_request {
-        return Ok(GolinkResolution::MetadataRequest(
-            parsed.short.trim_end_matches('+').to_string(),
-        ));
-    }
-
-    let lookup_value = lookup(&parsed.short)
-        .await
-        .ok_or_else(|| GolinkError::NotFound(parsed.short.clone()))?;
-
-    let expansion = expand(
-        &lookup_value,
-        &ExpandEnvironment {
-            path: parsed.remainder,
-        },
-    )?;
-
-    Ok(GolinkResolution::RedirectRequest {
-        url: expansion,
-        shortlink: parsed.short,
-    })
+    Resolver::default().resolve_async(input, &lookup).await
 }
 
 #[cfg(test)]
@@ -510,218 +659,315 @@ mod tests {
         None
     }
 
+    fn link(shortlink: &str) -> Link {
+        lookup(shortlink).unwrap().into()
+    }
+
     #[test]
     fn it_works() {
-        let computed = resolve("/test", &lookup);
+        let computed = resolve("/test", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/".to_string(),
-                shortlink: "test".to_string()
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_works_with_url() {
-        let computed = resolve("https://jil.im/test", &lookup);
+        let computed = resolve("https://jil.im/test", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/".to_string(),
-                shortlink: "test".to_string()
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_works_with_no_leading_slash() {
-        let computed = resolve("test", &lookup);
+        let computed = resolve("test", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/".to_string(),
-                shortlink: "test".to_string()
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_works_for_complex_url() {
-        let computed = resolve("/test2", &lookup);
+        let computed = resolve("/test2", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/test.html?a=b&c[]=d".to_string(),
-                shortlink: "test2".to_string()
+                shortlink: "test2".to_string(),
+                link: link("test2"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_ignores_case() {
-        let computed = resolve("/TEST", &lookup);
+        let computed = resolve("/TEST", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/".to_string(),
-                shortlink: "test".to_string()
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_ignores_hyphens() {
-        let computed = resolve("/t-est", &lookup);
+        let computed = resolve("/t-est", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/".to_string(),
-                shortlink: "test".to_string()
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_ignores_whitespace() {
-        let computed = resolve("/t est", &lookup);
+        let computed = resolve("/t est", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/".to_string(),
-                shortlink: "test".to_string()
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_returns_metadata_request() {
-        let computed = resolve("/test+", &lookup);
+        let computed = resolve("/test+", lookup);
         assert_eq!(
             computed,
-            Ok(GolinkResolution::MetadataRequest("test".to_string()))
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
         )
     }
 
     #[test]
     fn it_returns_correct_metadata_request_with_hyphens() {
-        let computed = resolve("/tEs-t+", &lookup);
+        let computed = resolve("/tEs-t+", lookup);
         assert_eq!(
             computed,
-            Ok(GolinkResolution::MetadataRequest("test".to_string()))
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        )
+    }
+
+    #[test]
+    fn it_fails_metadata_request_for_missing_link() {
+        let computed = resolve("/missing+", lookup);
+        assert_eq!(
+            computed,
+            Err(GolinkError::NotFound {
+                shortlink: "missing".to_string(),
+                suggestions: Vec::new(),
+            })
         )
     }
 
     #[test]
     fn it_does_not_append_remaining_path_segments_with_invalid_resolved_url() {
-        let computed = resolve("/abcd/a/b/c", &lookup);
+        let computed = resolve("/abcd/a/b/c", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "efgh/a/b/c".to_string(),
-                shortlink: "abcd".to_string()
+                shortlink: "abcd".to_string(),
+                link: link("abcd"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_appends_remaining_path_segments() {
-        let computed = resolve("/test/a/b/c", &lookup);
+        let computed = resolve("/test/a/b/c", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/a/b/c".to_string(),
-                shortlink: "test".to_string()
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_appends_remaining_path_segments_for_maps_url() {
-        let computed = resolve("/test2/a/b/c", &lookup);
+        let computed = resolve("/test2/a/b/c", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/test.html/a/b/c?a=b&c[]=d".to_string(),
-                shortlink: "test2".to_string()
+                shortlink: "test2".to_string(),
+                link: link("test2"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_uses_path_in_template() {
-        let computed = resolve("/prs/jameslittle230", &lookup);
+        let computed = resolve("/prs/jameslittle230", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "https://github.com/pulls?q=is:open+is:pr+review-requested:jameslittle230+archived:false".to_string(),
-                shortlink: "prs".to_string()
+                shortlink: "prs".to_string(),
+                link: link("prs"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        )
+    }
+
+    #[test]
+    fn it_escapes_path_in_template_for_the_query_string() {
+        let computed = resolve("/prs/o'brien&co", lookup);
+        assert_eq!(
+            computed,
+            Ok(GolinkResolution::RedirectRequest {
+                url: "https://github.com/pulls?q=is:open+is:pr+review-requested:o%27brien%26co+archived:false".to_string(),
+                shortlink: "prs".to_string(),
+                link: link("prs"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_uses_fallback_in_template() {
-        let computed = resolve("/prs", &lookup);
+        let computed = resolve("/prs", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "https://github.com/pulls?q=is:open+is:pr+review-requested:@me+archived:false"
                     .to_string(),
-                shortlink: "prs".to_string()
+                shortlink: "prs".to_string(),
+                link: link("prs"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_uses_fallback_in_template_with_trailing_slash() {
-        let computed = resolve("/prs/", &lookup);
+        let computed = resolve("/prs/", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "https://github.com/pulls?q=is:open+is:pr+review-requested:@me+archived:false"
                     .to_string(),
-                shortlink: "prs".to_string()
+                shortlink: "prs".to_string(),
+                link: link("prs"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn it_allows_the_long_url_to_not_be_a_valid_url() {
-        let computed = resolve("/abcd", &lookup);
+        let computed = resolve("/abcd", lookup);
         assert_eq!(
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "efgh".to_string(),
-                shortlink: "abcd".to_string()
+                shortlink: "abcd".to_string(),
+                link: link("abcd"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
 
     #[test]
     fn normalize_shortlink_extracts_first_segment() {
-        assert_eq!(normalize_shortlink("foo/bar"), "foo");
-        assert_eq!(normalize_shortlink("/foo/bar/baz"), "foo");
-        assert_eq!(normalize_shortlink("My-Service/docs"), "myservice");
-        assert_eq!(normalize_shortlink("FOO/BAR"), "foo");
-        assert_eq!(normalize_shortlink("my service/other"), "myservice");
+        assert_eq!(normalize_shortlink("foo/bar").unwrap(), "foo");
+        assert_eq!(normalize_shortlink("/foo/bar/baz").unwrap(), "foo");
+        assert_eq!(normalize_shortlink("My-Service/docs").unwrap(), "myservice");
+        assert_eq!(normalize_shortlink("FOO/BAR").unwrap(), "foo");
+        assert_eq!(
+            normalize_shortlink("my service/other").unwrap(),
+            "myservice"
+        );
+    }
+
+    #[test]
+    fn normalize_shortlink_rejects_invalid_utf8_like_resolve() {
+        assert_eq!(normalize_shortlink("a%FFb"), Err(GolinkError::InvalidInput));
+        assert_eq!(
+            normalize_shortlink_path("docs/a%FFb"),
+            Err(GolinkError::InvalidInput)
+        );
+        assert_eq!(resolve("/a%FFb", lookup), Err(GolinkError::InvalidInput));
+        assert_eq!(normalize_shortlink("caf%C3%A9").unwrap(), "café");
     }
 
     #[test]
     fn it_fails_with_invalid_input_url() {
-        let computed = resolve("a:3gb", &lookup);
+        let computed = resolve("a:3gb", lookup);
         assert!(matches!(computed, Err(GolinkError::InvalidInput)));
     }
 
     #[test]
     fn it_fails_with_empty_string() {
-        let computed = resolve("", &lookup);
+        let computed = resolve("", lookup);
         assert!(matches!(computed, Err(GolinkError::InvalidInput)));
     }
 
     #[test]
     fn it_fails_with_whitespace_only_string() {
-        let computed = resolve("  \n", &lookup);
+        let computed = resolve("  \n", lookup);
         assert!(matches!(computed, Err(GolinkError::InvalidInput)));
     }
 
@@ -737,7 +983,10 @@ mod tests {
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/".to_string(),
-                shortlink: "test".to_string()
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
@@ -753,7 +1002,10 @@ mod tests {
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "http://example.com/a/b/c".to_string(),
-                shortlink: "test".to_string()
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
@@ -769,7 +1021,10 @@ mod tests {
             computed,
             Ok(GolinkResolution::RedirectRequest {
                 url: "https://github.com/pulls?q=is:open+is:pr+review-requested:jameslittle230+archived:false".to_string(),
-                shortlink: "prs".to_string()
+                shortlink: "prs".to_string(),
+                link: link("prs"),
+                layer: LinkLayer::Global,
+                trimmed: None,
             })
         )
     }
@@ -783,7 +1038,12 @@ mod tests {
         .await;
         assert_eq!(
             computed,
-            Ok(GolinkResolution::MetadataRequest("test".to_string()))
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
         )
     }
 }
@@ -0,0 +1,438 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::normalize::names_match;
+use crate::{GolinkError, Normalizer, User};
+
+/// A stored shortlink: the long URL plus everything else known about it.
+///
+/// Stores return a `Link` from a lookup, and the resolver hands it back in
+/// [`GolinkResolution`](crate::GolinkResolution) so that handlers can render metadata
+/// pages or apply policy without a second round trip to the store. Stores that only
+/// know the long URL can return a `String`, which converts into a `Link` with default
+/// metadata.
+///
+/// # Examples
+///
+/// ```
+/// use golink::{Link, RedirectKind};
+///
+/// let link = Link::new("https://example.com/")
+///     .with_owner("jameslittle230")
+///     .with_description("The example homepage")
+///     .with_tags(["docs", "example"])
+///     .with_redirect_kind(RedirectKind::Permanent);
+///
+/// assert_eq!(link.url, "https://example.com/");
+/// assert_eq!(link.redirect_kind.status_code(), 301);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Link {
+    /// The long URL (or URL template) that the shortlink expands to.
+    pub url: String,
+
+    /// The user or team responsible for the link.
+    #[serde(default)]
+    pub owner: Option<String>,
+
+    /// A human-readable description of where the link goes.
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// When the link was created.
+    #[serde(default)]
+    pub created_at: Option<SystemTime>,
+
+    /// When the link was last edited.
+    #[serde(default)]
+    pub updated_at: Option<SystemTime>,
+
+    /// Free-form tags for grouping and searching links.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Which HTTP redirect the link should be served with.
+    #[serde(default)]
+    pub redirect_kind: RedirectKind,
+
+    /// Who should be able to discover the link.
+    #[serde(default)]
+    pub visibility: Visibility,
+
+    /// Who may resolve the link. The resolver checks it against the requesting user
+    /// and fails with [`GolinkError::Forbidden`](crate::GolinkError::Forbidden) for
+    /// anyone else.
+    #[serde(default)]
+    pub access: Access,
+
+    /// The link's usage signature, e.g. `gh/{org}/{repo}/{number?}`.
+    ///
+    /// Each `{name}` after the shortlink binds the next remaining path segment of a
+    /// request to `name` in the long URL template, so the link can use `{ org }` rather
+    /// than `{ segments.0 }`. Parameters marked `?` are optional and bind to an empty
+    /// string when absent; a request missing a required parameter fails with
+    /// [`GolinkError::MissingParameters`](crate::GolinkError::MissingParameters).
+    #[serde(default)]
+    pub signature: Option<String>,
+
+    /// Whether the long URL is a template, a literal URL, or should be detected.
+    #[serde(default)]
+    pub kind: LinkKind,
+
+    /// The go link service that answered the lookup, e.g. `https://go.example.com`,
+    /// for links fetched from another instance by a federated store. `None` for links
+    /// from the local store.
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+impl Link {
+    /// Creates a link to `url` with no other metadata.
+    #[must_use]
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            owner: None,
+            description: None,
+            created_at: None,
+            updated_at: None,
+            tags: Vec::new(),
+            redirect_kind: RedirectKind::default(),
+            visibility: Visibility::default(),
+            access: Access::default(),
+            signature: None,
+            kind: LinkKind::default(),
+            source: None,
+        }
+    }
+
+    /// Returns the shortlink this link is an alias for, if any.
+    ///
+    /// A link whose long value is written `go/<shortlink>` or `alias:<shortlink>`
+    /// points at another shortlink, and the resolver looks that one up instead. The
+    /// target may carry extra path segments (`go/docs/api`), which are placed before
+    /// any remaining path segments of the request.
+    ///
+    /// ```
+    /// use golink::Link;
+    ///
+    /// assert_eq!(Link::new("go/standup").alias_target(), Some("standup"));
+    /// assert_eq!(Link::new("alias:docs/api").alias_target(), Some("docs/api"));
+    /// assert_eq!(Link::new("https://example.com/").alias_target(), None);
+    /// ```
+    #[must_use]
+    pub fn alias_target(&self) -> Option<&str> {
+        self.url
+            .strip_prefix("alias:")
+            .or_else(|| self.url.strip_prefix("go/"))
+            .map(|target| target.trim_start_matches('/'))
+            .filter(|target| !target.is_empty())
+    }
+
+    /// Sets the link's owner.
+    #[must_use]
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    /// Sets the link's description.
+    #[must_use]
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Sets when the link was created.
+    #[must_use]
+    pub fn with_created_at(mut self, created_at: SystemTime) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    /// Sets when the link was last edited.
+    #[must_use]
+    pub fn with_updated_at(mut self, updated_at: SystemTime) -> Self {
+        self.updated_at = Some(updated_at);
+        self
+    }
+
+    /// Sets the link's tags.
+    #[must_use]
+    pub fn with_tags<I, T>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets which HTTP redirect the link should be served with.
+    #[must_use]
+    pub fn with_redirect_kind(mut self, redirect_kind: RedirectKind) -> Self {
+        self.redirect_kind = redirect_kind;
+        self
+    }
+
+    /// Sets who should be able to discover the link.
+    #[must_use]
+    pub fn with_visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Sets who may resolve the link.
+    #[must_use]
+    pub fn with_access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Returns whether `user` (or an anonymous request, for `None`) may resolve the
+    /// link, i.e. whether its [`access`](Self::access) admits them.
+    #[must_use]
+    pub fn is_accessible_by(&self, user: Option<&User>) -> bool {
+        self.access.admits(user)
+    }
+
+    /// Sets whether the long URL is a template or a literal URL.
+    #[must_use]
+    pub fn with_kind(mut self, kind: LinkKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Sets the link's usage signature, which names its path parameters.
+    #[must_use]
+    pub fn with_signature(mut self, signature: impl Into<String>) -> Self {
+        self.signature = Some(signature.into());
+        self
+    }
+
+    /// Sets the go link service that answered the lookup.
+    #[must_use]
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+impl From<String> for Link {
+    fn from(url: String) -> Self {
+        Self::new(url)
+    }
+}
+
+impl From<&str> for Link {
+    fn from(url: &str) -> Self {
+        Self::new(url)
+    }
+}
+
+/// Whether a link's long URL is a template or a literal URL.
+///
+/// Long URLs for dashboards often contain literal braces, such as a JSON filter in
+/// `?filter={"a":1}`. Mark those links [`Literal`](LinkKind::Literal) so they are never
+/// rendered, or check how a link will be treated with
+/// [`validate_link()`](crate::validate_link) before storing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// Treat the long URL as a template only if it contains template syntax: a
+    /// `{{ }}` block, a `\{` escape, or a `{ }` placeholder naming a template
+    /// variable such as `path` or one of the link's signature parameters. Anything
+    /// else in braces is left alone.
+    #[default]
+    Auto,
+
+    /// Always render the long URL as a template. Remaining path segments are only
+    /// used where the template places them.
+    Template,
+
+    /// Never render the long URL; remaining path segments are appended to it.
+    Literal,
+}
+
+/// The HTTP redirect a link should be served with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectKind {
+    /// A temporary redirect, so browsers check back with the go service on every visit
+    /// and edits to the link take effect immediately.
+    #[default]
+    Temporary,
+
+    /// A permanent redirect, which browsers may cache indefinitely.
+    Permanent,
+}
+
+impl RedirectKind {
+    /// The HTTP status code for this kind of redirect: 302 for temporary, 301 for permanent.
+    #[must_use]
+    pub fn status_code(self) -> u16 {
+        match self {
+            RedirectKind::Temporary => 302,
+            RedirectKind::Permanent => 301,
+        }
+    }
+}
+
+/// Who should be able to discover a link.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// The link appears in directories and search.
+    #[default]
+    Public,
+
+    /// The link resolves for anyone who knows it, but should be left out of
+    /// directories and search.
+    Unlisted,
+}
+
+/// Who may resolve a link.
+///
+/// Unlike [`Visibility`], which only affects whether a link is listed, access is
+/// enforced: the resolver checks it against the [`User`] in the request's
+/// [`RequestContext`](crate::RequestContext) and fails with
+/// [`GolinkError::Forbidden`](crate::GolinkError::Forbidden), without revealing the
+/// long URL, when it doesn't admit them. A link's [owner](Link::owner) is free text and
+/// gets no access of its own, so list them here too if they should resolve the link.
+///
+/// Logins and group names are compared ignoring case, including non-ASCII case, as the
+/// default [`Normalizer`] does.
+///
+/// # Examples
+///
+/// ```
+/// use golink::{Access, User};
+///
+/// let access = Access::restricted(["alice"], ["hr"]);
+/// assert!(access.admits(Some(&User::new("alice"))));
+/// assert!(access.admits(Some(&User::new("bob").with_groups(["HR"]))));
+/// assert!(access.admits(Some(&User::new("ALICE"))));
+/// assert!(!access.admits(Some(&User::new("bob"))));
+/// assert!(!access.admits(None));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    /// Anyone who can reach the service, signed in or not.
+    #[default]
+    Public,
+
+    /// Any signed-in user.
+    Authenticated,
+
+    /// Only the listed users and members of the listed groups.
+    Restricted {
+        /// The logins of users who may resolve the link.
+        #[serde(default)]
+        users: Vec<String>,
+        /// The groups whose members may resolve the link.
+        #[serde(default)]
+        groups: Vec<String>,
+    },
+}
+
+impl Access {
+    /// Creates an [`Access::Restricted`] policy for the given users and groups.
+    #[must_use]
+    pub fn restricted<U, G>(users: U, groups: G) -> Self
+    where
+        U: IntoIterator,
+        U::Item: Into<String>,
+        G: IntoIterator,
+        G::Item: Into<String>,
+    {
+        Access::Restricted {
+            users: users.into_iter().map(Into::into).collect(),
+            groups: groups.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Returns whether this policy admits `user`, or an anonymous request for `None`.
+    #[must_use]
+    pub fn admits(&self, user: Option<&User>) -> bool {
+        match (self, user) {
+            (Access::Public, _) => true,
+            (_, None) => false,
+            (Access::Authenticated, Some(_)) => true,
+            (Access::Restricted { users, groups }, Some(user)) => {
+                users.iter().any(|login| names_match(login, &user.login))
+                    || groups.iter().any(|group| user.in_group(group))
+            }
+        }
+    }
+}
+
+/// The namespace of the store a link was found in.
+///
+/// With [`ResolverBuilder::layered_links`](crate::ResolverBuilder::layered_links)
+/// enabled, the resolver checks the requesting user's personal links first, then
+/// links shared by each of their groups, then the global links everyone sees. Each
+/// layer stores its links under a prefixed key; see [`LinkLayer::key`].
+///
+/// # Examples
+///
+/// ```
+/// use golink::LinkLayer;
+///
+/// assert_eq!(LinkLayer::Personal("alice".to_string()).key("notes"), "~alice/notes");
+/// assert_eq!(LinkLayer::Group("eng".to_string()).key("standup"), "@eng/standup");
+/// assert_eq!(LinkLayer::Global.key("standup"), "standup");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkLayer {
+    /// A user's personal link, stored as `~login/shortlink`. Holds the user's
+    /// normalized login.
+    Personal(String),
+
+    /// A link shared by the members of a group, stored as `@group/shortlink`. Holds
+    /// the normalized group name.
+    Group(String),
+
+    /// A link everyone sees, stored under the bare shortlink.
+    #[default]
+    Global,
+}
+
+impl LinkLayer {
+    /// Returns the store key of a normalized `shortlink` in this layer.
+    ///
+    /// Use it with [`normalize_shortlink()`](crate::normalize_shortlink) when storing
+    /// personal or group links.
+    #[must_use]
+    pub fn key(&self, shortlink: &str) -> String {
+        match self {
+            LinkLayer::Personal(login) => format!("~{login}/{shortlink}"),
+            LinkLayer::Group(group) => format!("@{group}/{shortlink}"),
+            LinkLayer::Global => shortlink.to_string(),
+        }
+    }
+
+    /// Parses a namespace segment written at the start of a request, like the `~alice`
+    /// of `go/~alice/notes`.
+    /// The name is decoded and normalized like the rest of the request path, whatever
+    /// the normalizer strips from the prefix.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidInput`: The name's escapes aren't valid UTF-8
+    pub(crate) fn from_namespace(
+        segment: &str,
+        normalizer: &Normalizer,
+    ) -> Result<Option<Self>, GolinkError> {
+        let layer: fn(String) -> Self = match segment.chars().next() {
+            Some('~') => LinkLayer::Personal,
+            Some('@') => LinkLayer::Group,
+            _ => return Ok(None),
+        };
+        let name = normalizer.normalize_segment(&segment[1..])?;
+        Ok((!name.is_empty()).then(|| layer(name)))
+    }
+}
@@ -0,0 +1,479 @@
+use std::ops::RangeInclusive;
+
+use percent_encoding::percent_decode_str;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::GolinkError;
+
+/// The Unicode `Dash_Punctuation` characters, plus the minus sign.
+const DASHES: &[char] = &[
+    '-',
+    '\u{058A}',
+    '\u{05BE}',
+    '\u{1400}',
+    '\u{1806}',
+    '\u{2010}',
+    '\u{2011}',
+    '\u{2012}',
+    '\u{2013}',
+    '\u{2014}',
+    '\u{2015}',
+    '\u{2212}',
+    '\u{2E17}',
+    '\u{2E1A}',
+    '\u{2E3A}',
+    '\u{2E3B}',
+    '\u{2E40}',
+    '\u{2E5D}',
+    '\u{301C}',
+    '\u{3030}',
+    '\u{30A0}',
+    '\u{FE31}',
+    '\u{FE32}',
+    '\u{FE58}',
+    '\u{FE63}',
+    '\u{FF0D}',
+    '\u{10EAD}',
+];
+
+/// Rules for turning a user-typed shortlink into the key passed to your lookup function.
+///
+/// The default rules apply Unicode compatibility normalization (NFKC) and full case
+/// folding, then remove dashes and whitespace, so `My-Service`, `my%20service`,
+/// `my service` and `myservice` all normalize to `myservice`. Full-width letters, en
+/// dashes, non-breaking spaces and `ß` (folded to `ss`) are just as forgiving.
+/// Underscores, dots and everything else are kept.
+///
+/// A character is removed if it belongs to a [class](CharClass) passed to
+/// [`strip`](Self::strip) and to none passed to [`keep`](Self::keep), so broad classes
+/// can be narrowed down.
+///
+/// Configure a [`Resolver`](crate::Resolver) with the same normalizer you use to store
+/// shortlinks, or store them through
+/// [`Resolver::normalize_shortlink`](crate::Resolver::normalize_shortlink), so that
+/// stored keys match what the resolver looks up.
+///
+/// # Examples
+///
+/// ```
+/// use golink::{CharClass, Normalizer};
+///
+/// let normalizer = Normalizer::new().case_sensitive(true);
+/// assert_eq!(normalizer.normalize_shortlink("/Ab-Cd/docs")?, "AbCd");
+///
+/// let normalizer = Normalizer::new().strip_underscores(true);
+/// assert_eq!(normalizer.normalize_shortlink("foo_bar")?, "foobar");
+///
+/// let normalizer = Normalizer::new()
+///     .strip(CharClass::Punctuation)
+///     .keep(CharClass::Char('.'));
+/// assert_eq!(normalizer.normalize_shortlink("v1.2_(beta)!")?, "v1.2beta");
+/// # Ok::<(), golink::GolinkError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalizer {
+    case_sensitive: bool,
+    unicode: bool,
+    strip: Vec<CharClass>,
+    keep: Vec<CharClass>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Self {
+            case_sensitive: false,
+            unicode: true,
+            strip: vec![CharClass::Dash, CharClass::Whitespace],
+            keep: Vec::new(),
+        }
+    }
+}
+
+/// A set of characters for a [`Normalizer`] to strip from or keep in shortlinks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CharClass {
+    /// A single character.
+    Char(char),
+
+    /// Every character in an inclusive range, like `'0'..='9'`.
+    Range(RangeInclusive<char>),
+
+    /// Unicode whitespace, including non-breaking spaces.
+    Whitespace,
+
+    /// Unicode dashes, like the hyphen-minus, en dash, em dash and minus sign.
+    Dash,
+
+    /// ASCII punctuation, like `-`, `_`, `.` and `!`.
+    Punctuation,
+
+    /// Everything that isn't a Unicode letter or digit.
+    NonAlphanumeric,
+}
+
+impl CharClass {
+    /// Returns whether `c` belongs to this class.
+    #[must_use]
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            CharClass::Char(member) => c == *member,
+            CharClass::Range(range) => range.contains(&c),
+            CharClass::Whitespace => c.is_whitespace(),
+            CharClass::Dash => DASHES.contains(&c),
+            CharClass::Punctuation => c.is_ascii_punctuation(),
+            CharClass::NonAlphanumeric => !c.is_alphanumeric(),
+        }
+    }
+}
+
+impl From<char> for CharClass {
+    fn from(c: char) -> Self {
+        CharClass::Char(c)
+    }
+}
+
+impl From<RangeInclusive<char>> for CharClass {
+    fn from(range: RangeInclusive<char>) -> Self {
+        CharClass::Range(range)
+    }
+}
+
+impl Normalizer {
+    /// Creates a normalizer with the default rules.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preserves letter case instead of lowercasing, for instances that host
+    /// case-sensitive generated codes.
+    #[must_use]
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Applies Unicode compatibility normalization (NFKC) and full case folding, so
+    /// that full-width letters, ligatures and characters like `ß` match what users
+    /// think they typed. Defaults to `true`; when disabled, only ASCII letters are
+    /// lowercased.
+    #[must_use]
+    pub fn unicode(mut self, unicode: bool) -> Self {
+        self.unicode = unicode;
+        self
+    }
+
+    /// Removes underscores, so that `foo_bar` and `foobar` are the same shortlink.
+    /// Defaults to `false`.
+    #[must_use]
+    pub fn strip_underscores(self, strip_underscores: bool) -> Self {
+        self.strip_class(CharClass::Char('_'), strip_underscores)
+    }
+
+    /// Removes dots, so that `v1.2` and `v12` are the same shortlink. Defaults to
+    /// `false`.
+    #[must_use]
+    pub fn strip_dots(self, strip_dots: bool) -> Self {
+        self.strip_class(CharClass::Char('.'), strip_dots)
+    }
+
+    /// Removes hyphens and other [dashes](CharClass::Dash). Defaults to `true`.
+    #[must_use]
+    pub fn strip_hyphens(self, strip_hyphens: bool) -> Self {
+        self.strip_class(CharClass::Dash, strip_hyphens)
+    }
+
+    /// Removes spaces and other [whitespace](CharClass::Whitespace). Defaults to `true`.
+    #[must_use]
+    pub fn strip_spaces(self, strip_spaces: bool) -> Self {
+        self.strip_class(CharClass::Whitespace, strip_spaces)
+    }
+
+    /// Removes the characters in `class`, unless they're also in a class passed to
+    /// [`keep`](Self::keep).
+    #[must_use]
+    pub fn strip(mut self, class: impl Into<CharClass>) -> Self {
+        self.strip.push(class.into());
+        self
+    }
+
+    /// Keeps the characters in `class`, even if they're in a class passed to
+    /// [`strip`](Self::strip).
+    #[must_use]
+    pub fn keep(mut self, class: impl Into<CharClass>) -> Self {
+        self.keep.push(class.into());
+        self
+    }
+
+    fn strip_class(mut self, class: CharClass, strip: bool) -> Self {
+        self.strip.retain(|stripped| *stripped != class);
+        self.keep.retain(|kept| *kept != class);
+        if strip {
+            self.strip.push(class);
+        } else {
+            self.keep.push(class);
+        }
+        self
+    }
+
+    fn is_stripped(&self, c: char) -> bool {
+        self.strip.iter().any(|class| class.contains(c))
+            && !self.keep.iter().any(|class| class.contains(c))
+    }
+
+    /// Normalizes the first path segment of `input` using these rules.
+    ///
+    /// See [`normalize_shortlink`](crate::normalize_shortlink) for details.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidInput`: The segment's escapes aren't valid UTF-8
+    pub fn normalize_shortlink(&self, input: &str) -> Result<String, GolinkError> {
+        // Extract first non-empty path segment
+        let first_segment = input
+            .trim_start_matches('/')
+            .split('/')
+            .next()
+            .unwrap_or("");
+
+        self.normalize_segment(first_segment)
+    }
+
+    /// Normalizes every path segment of `input` using these rules, joining them with `/`.
+    ///
+    /// See [`normalize_shortlink_path`](crate::normalize_shortlink_path) for details.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidInput`: A segment's escapes aren't valid UTF-8
+    pub fn normalize_shortlink_path(&self, input: &str) -> Result<String, GolinkError> {
+        self.normalize_segments(input, usize::MAX)
+    }
+
+    /// Normalizes up to `limit` leading path segments of `input`, joining them with `/`.
+    ///
+    /// Stops at the first segment that is empty after normalization, matching how the
+    /// resolver builds hierarchical keys.
+    pub(crate) fn normalize_segments(
+        &self,
+        input: &str,
+        limit: usize,
+    ) -> Result<String, GolinkError> {
+        Ok(input
+            .trim_start_matches('/')
+            .split('/')
+            .take(limit)
+            .map(|segment| self.normalize_segment(segment))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .take_while(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+
+    /// Returns the confusable skeleton of a shortlink: its normalized form with every
+    /// character replaced by the prototype it can be mistaken for, following
+    /// [Unicode TR39](https://www.unicode.org/reports/tr39/#Confusable_Detection).
+    ///
+    /// Two shortlinks with the same skeleton look alike, like `paypal` and `pаypal`
+    /// with a Cyrillic `а`. Compare a new shortlink's skeleton with those of existing
+    /// links before storing it to stop lookalike links from being registered.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidInput`: A segment's escapes aren't valid UTF-8
+    pub fn skeleton(&self, input: &str) -> Result<String, GolinkError> {
+        let normalized = self.normalize_shortlink_path(input)?;
+        // Prototypes can be uppercase (`0` looks like `O`), so normalize them too
+        let skeleton: String = unicode_security::skeleton(&normalized).collect();
+        self.normalize_shortlink_path(&skeleton)
+    }
+
+    /// Returns whether two shortlinks look alike: they normalize differently, but have
+    /// the same [skeleton](Self::skeleton). A shortlink that doesn't normalize, because
+    /// its escapes aren't valid UTF-8, looks like nothing else.
+    ///
+    /// ```
+    /// use golink::Normalizer;
+    ///
+    /// let normalizer = Normalizer::new();
+    /// assert!(normalizer.is_confusable("paypal", "p\u{0430}ypal"));
+    /// assert!(!normalizer.is_confusable("paypal", "Pay-Pal"));
+    /// assert!(!normalizer.is_confusable("paypal", "paypals"));
+    /// assert!(!normalizer.is_confusable("a%FF", "a%FE"));
+    /// ```
+    #[must_use]
+    pub fn is_confusable(&self, a: &str, b: &str) -> bool {
+        let (Ok(a_skeleton), Ok(b_skeleton)) = (self.skeleton(a), self.skeleton(b)) else {
+            return false;
+        };
+        a_skeleton == b_skeleton
+            && self.normalize_shortlink_path(a) != self.normalize_shortlink_path(b)
+    }
+
+    /// Normalizes a single shortlink segment, percent-decoding it first.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidInput`: The segment's escapes aren't valid UTF-8
+    pub(crate) fn normalize_segment(&self, segment: &str) -> Result<String, GolinkError> {
+        let decoded = percent_decode_str(segment)
+            .decode_utf8()
+            .map_err(|_| GolinkError::InvalidInput)?;
+        Ok(self.normalize_decoded(&decoded))
+    }
+
+    fn normalize_decoded(&self, segment: &str) -> String {
+        let segment = match (self.unicode, self.case_sensitive) {
+            (true, true) => segment.nfkc().collect(),
+            // NFKC_Casefold, approximated as in the Unicode standard's definition of
+            // compatibility caseless matching
+            (true, false) => fold_case(segment),
+            (false, true) => segment.to_string(),
+            (false, false) => segment.to_ascii_lowercase(),
+        };
+
+        segment.chars().filter(|c| !self.is_stripped(*c)).collect()
+    }
+}
+
+/// Folds the case of `text` for caseless matching: NFKC_Casefold, approximated as in
+/// the Unicode standard's definition of compatibility caseless matching.
+fn fold_case(text: &str) -> String {
+    caseless::default_case_fold_str(&text.nfkc().collect::<String>())
+        .nfkc()
+        .collect()
+}
+
+/// Returns whether two names, like logins or group names, match when case is ignored
+/// the way the default [`Normalizer`] ignores it.
+pub(crate) fn names_match(a: &str, b: &str) -> bool {
+    a == b || fold_case(a) == fold_case(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_strips_hyphens_and_spaces_by_default() {
+        let normalizer = Normalizer::default();
+        assert_eq!(
+            normalizer.normalize_segment("My-Service").unwrap(),
+            "myservice"
+        );
+        assert_eq!(
+            normalizer.normalize_segment("my%20service").unwrap(),
+            "myservice"
+        );
+        assert_eq!(
+            normalizer.normalize_segment("foo_bar.baz").unwrap(),
+            "foo_bar.baz"
+        );
+    }
+
+    #[test]
+    fn it_decodes_percent_escapes() {
+        let normalizer = Normalizer::default();
+        assert_eq!(
+            normalizer.normalize_segment("my%2Dservice").unwrap(),
+            "myservice"
+        );
+        assert_eq!(
+            normalizer.normalize_segment("%C3%9Cn%C3%AFcode").unwrap(),
+            "ünïcode"
+        );
+        assert_eq!(normalizer.normalize_segment("100%").unwrap(), "100%");
+        assert_eq!(
+            normalizer.normalize_segment("a%FFb"),
+            Err(GolinkError::InvalidInput)
+        );
+        assert_eq!(
+            normalizer.normalize_shortlink_path("docs/a%FFb"),
+            Err(GolinkError::InvalidInput)
+        );
+        assert_eq!(normalizer.skeleton("a%FFb"), Err(GolinkError::InvalidInput));
+    }
+
+    #[test]
+    fn it_toggles_the_built_in_rules() {
+        let normalizer = Normalizer::new().strip_underscores(true).strip_dots(true);
+        assert_eq!(
+            normalizer.normalize_segment("Foo_Bar.baz").unwrap(),
+            "foobarbaz"
+        );
+
+        let normalizer = Normalizer::new().strip_hyphens(false).strip_spaces(false);
+        assert_eq!(
+            normalizer.normalize_segment("my-service%20x").unwrap(),
+            "my-service x"
+        );
+
+        let normalizer = Normalizer::new()
+            .strip_underscores(true)
+            .strip_underscores(false);
+        assert_eq!(normalizer.normalize_segment("foo_bar").unwrap(), "foo_bar");
+    }
+
+    #[test]
+    fn it_normalizes_unicode() {
+        let normalizer = Normalizer::default();
+        assert_eq!(normalizer.normalize_segment("Ünïcode").unwrap(), "ünïcode");
+        assert_eq!(
+            normalizer.normalize_segment("U\u{308}nïcode").unwrap(),
+            "ünïcode"
+        );
+        assert_eq!(
+            normalizer
+                .normalize_segment("ＭＹ－ＳＥＲＶＩＣＥ")
+                .unwrap(),
+            "myservice"
+        );
+        assert_eq!(
+            normalizer.normalize_segment("My–Service").unwrap(),
+            "myservice"
+        );
+        assert_eq!(
+            normalizer.normalize_segment("my\u{a0}service").unwrap(),
+            "myservice"
+        );
+        assert_eq!(normalizer.normalize_segment("Straße").unwrap(), "strasse");
+        assert_eq!(normalizer.normalize_segment("ﬁle").unwrap(), "file");
+
+        let normalizer = Normalizer::new().case_sensitive(true);
+        assert_eq!(normalizer.normalize_segment("ＡＢ－ｃｄ").unwrap(), "ABcd");
+
+        let normalizer = Normalizer::new().unicode(false);
+        assert_eq!(normalizer.normalize_segment("ÜNÏ-Code").unwrap(), "ÜnÏcode");
+    }
+
+    #[test]
+    fn it_detects_confusable_shortlinks() {
+        let normalizer = Normalizer::default();
+        assert_eq!(
+            normalizer.skeleton("/p\u{0430}yp\u{0430}l/docs"),
+            Ok("paypal/docs".to_string())
+        );
+        assert!(normalizer.is_confusable("g00gle", "gOOgle"));
+        assert!(!normalizer.is_confusable("google", "Goo-gle"));
+    }
+
+    #[test]
+    fn it_applies_custom_character_classes() {
+        let normalizer = Normalizer::new().strip(CharClass::NonAlphanumeric);
+        assert_eq!(normalizer.normalize_segment("a~b@c_d.é!").unwrap(), "abcdé");
+
+        let normalizer = Normalizer::new()
+            .case_sensitive(true)
+            .strip('0'..='9')
+            .keep('7');
+        assert_eq!(normalizer.normalize_segment("Ab-1237").unwrap(), "Ab7");
+
+        let normalizer = Normalizer::new()
+            .strip(CharClass::Whitespace)
+            .keep(CharClass::Char(' '));
+        assert_eq!(normalizer.normalize_segment("a\tb c").unwrap(), "ab c");
+    }
+}
@@ -0,0 +1,1948 @@
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use itertools::Itertools;
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::compiled::CompiledCache;
+use crate::expand::{CompiledUrl, PathAppend, QueryPassthrough, TemplateEngine, pass_through};
+use crate::expander::Expander;
+use crate::fallback::upstream_url;
+use crate::store::{AsyncLinkStore, Blocking, LinkStore};
+use crate::suggest::suggest;
+use crate::{
+    CompiledLink, FallbackPolicy, GolinkError, GolinkResolution, Link, LinkKind, LinkLayer,
+    LookupError, Normalizer, RequestContext, SafetyPolicy,
+};
+
+const DEFAULT_METADATA_SUFFIX: &str = "+";
+const DEFAULT_MAX_INPUT_LENGTH: usize = 2048;
+const DEFAULT_MAX_ALIAS_DEPTH: usize = 8;
+const DEFAULT_MAX_SHORTLINK_SEGMENTS: usize = 1;
+const DEFAULT_COMPILED_LINK_CAPACITY: usize = 1024;
+
+/// Punctuation that ends up after a link pasted into a sentence.
+const TRAILING_PUNCTUATION: &[char] =
+    &['.', ',', ';', ':', '!', '?', ')', ']', '}', '>', '"', '\''];
+
+/// A configured shortlink resolver.
+///
+/// A `Resolver` owns every policy decision made while resolving a shortlink, so
+/// you can build one at startup and share it across requests. Use
+/// [`Resolver::builder()`] to change the defaults; [`Resolver::default()`] behaves
+/// exactly like the free [`resolve()`](crate::resolve) and
+/// [`resolve_async()`](crate::resolve_async) functions.
+///
+/// # Examples
+///
+/// ```
+/// use golink::{GolinkResolution, Link, LinkLayer, PathAppend, Resolver};
+///
+/// let resolver = Resolver::builder()
+///     .metadata_suffix("!")
+///     .path_append(PathAppend::Discard)
+///     .build();
+///
+/// let lookup = |shortlink: &str| (shortlink == "docs").then(|| "https://docs.example.com/".to_string());
+///
+/// assert_eq!(
+///     resolver.resolve("/docs/api", &lookup),
+///     Ok(GolinkResolution::RedirectRequest {
+///         url: "https://docs.example.com/".to_string(),
+///         shortlink: "docs".to_string(),
+///         link: Link::new("https://docs.example.com/"),
+///         layer: LinkLayer::Global,
+///         trimmed: None,
+///     })
+/// );
+/// assert_eq!(
+///     resolver.resolve("/docs!", &lookup),
+///     Ok(GolinkResolution::MetadataRequest {
+///         shortlink: "docs".to_string(),
+///         link: Link::new("https://docs.example.com/"),
+///         layer: LinkLayer::Global,
+///         trimmed: None,
+///     })
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    normalizer: Normalizer,
+    metadata_suffix: String,
+    path_append: PathAppend,
+    expander: Arc<dyn Expander>,
+    max_input_length: usize,
+    max_alias_depth: usize,
+    max_shortlink_segments: usize,
+    query_passthrough: QueryPassthrough,
+    fragment_passthrough: bool,
+    layered_links: bool,
+    trim_punctuation: bool,
+    suggestions: usize,
+    fallback: FallbackPolicy,
+    safety_policy: SafetyPolicy,
+    compiled: Arc<CompiledCache>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        ResolverBuilder::new().build()
+    }
+}
+
+/// Builder for [`Resolver`].
+#[derive(Debug, Clone)]
+pub struct ResolverBuilder {
+    normalizer: Normalizer,
+    metadata_suffix: String,
+    path_append: PathAppend,
+    expander: Arc<dyn Expander>,
+    max_input_length: usize,
+    max_alias_depth: usize,
+    max_shortlink_segments: usize,
+    query_passthrough: QueryPassthrough,
+    fragment_passthrough: bool,
+    layered_links: bool,
+    trim_punctuation: bool,
+    suggestions: usize,
+    fallback: FallbackPolicy,
+    safety_policy: SafetyPolicy,
+    compiled_link_capacity: usize,
+}
+
+impl Default for ResolverBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResolverBuilder {
+    /// Creates a builder with the default policies.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            normalizer: Normalizer::default(),
+            metadata_suffix: DEFAULT_METADATA_SUFFIX.to_string(),
+            path_append: PathAppend::default(),
+            expander: Arc::new(TemplateEngine::default()),
+            max_input_length: DEFAULT_MAX_INPUT_LENGTH,
+            max_alias_depth: DEFAULT_MAX_ALIAS_DEPTH,
+            max_shortlink_segments: DEFAULT_MAX_SHORTLINK_SEGMENTS,
+            query_passthrough: QueryPassthrough::default(),
+            fragment_passthrough: false,
+            layered_links: false,
+            trim_punctuation: true,
+            suggestions: 0,
+            fallback: FallbackPolicy::default(),
+            safety_policy: SafetyPolicy::default(),
+            compiled_link_capacity: DEFAULT_COMPILED_LINK_CAPACITY,
+        }
+    }
+
+    /// Sets the rules used to turn the requested shortlink into a lookup key.
+    #[must_use]
+    pub fn normalizer(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Sets the suffix that turns a request into a
+    /// [`MetadataRequest`](GolinkResolution::MetadataRequest). Defaults to `+`.
+    ///
+    /// An empty suffix disables metadata requests entirely.
+    #[must_use]
+    pub fn metadata_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.metadata_suffix = suffix.into();
+        self
+    }
+
+    /// Sets how remaining path segments are combined with long URLs that don't
+    /// use them in a template. Defaults to [`PathAppend::Append`].
+    #[must_use]
+    pub fn path_append(mut self, path_append: PathAppend) -> Self {
+        self.path_append = path_append;
+        self
+    }
+
+    /// Sets the built-in template engine used to render long URLs. Defaults to
+    /// [`TemplateEngine::TinyTemplate`].
+    #[must_use]
+    pub fn template_engine(self, template_engine: TemplateEngine) -> Self {
+        self.expander(template_engine)
+    }
+
+    /// Sets a custom [`Expander`] to render long URLs with, in place of a built-in
+    /// [`TemplateEngine`].
+    #[must_use]
+    pub fn expander(mut self, expander: impl Expander + 'static) -> Self {
+        self.expander = Arc::new(expander);
+        self
+    }
+
+    /// Sets the longest input, in bytes, that the resolver will attempt to parse.
+    /// Longer inputs fail with [`GolinkError::InvalidInput`]. Defaults to 2048.
+    #[must_use]
+    pub fn max_input_length(mut self, max_input_length: usize) -> Self {
+        self.max_input_length = max_input_length;
+        self
+    }
+
+    /// Sets how many [alias](Link::alias_target) hops the resolver will follow before
+    /// failing with [`GolinkError::AliasCycle`]. Defaults to 8.
+    ///
+    /// A depth of 0 disables aliases: long values like `go/other` are then treated
+    /// as literal long URLs.
+    #[must_use]
+    pub fn max_alias_depth(mut self, max_alias_depth: usize) -> Self {
+        self.max_alias_depth = max_alias_depth;
+        self
+    }
+
+    /// Enables hierarchical shortlinks made of up to `max_shortlink_segments` path
+    /// segments. Defaults to 1, where only the first segment is the shortlink.
+    ///
+    /// With a higher limit, the resolver tries the longest candidate first: with
+    /// `max_shortlink_segments(2)`, `go/team/oncall/today` is looked up as `team/oncall`,
+    /// then `team`, and the segments after the first match become the remaining path.
+    /// Keys are normalized segment by segment and joined with `/`, so store hierarchical
+    /// links under the output of [`normalize_shortlink_path()`](crate::normalize_shortlink_path).
+    #[must_use]
+    pub fn max_shortlink_segments(mut self, max_shortlink_segments: usize) -> Self {
+        self.max_shortlink_segments = max_shortlink_segments;
+        self
+    }
+
+    /// Sets how the query string of a request is combined with the long URL's own.
+    /// Defaults to [`QueryPassthrough::Discard`].
+    ///
+    /// Templates can read the request's query parameters as `{ query.name }` whatever
+    /// this is set to.
+    #[must_use]
+    pub fn query_passthrough(mut self, query_passthrough: QueryPassthrough) -> Self {
+        self.query_passthrough = query_passthrough;
+        self
+    }
+
+    /// Carries the fragment of a request (`go/docs#install`) over to long URLs that
+    /// don't have one of their own. Defaults to `false`.
+    #[must_use]
+    pub fn fragment_passthrough(mut self, fragment_passthrough: bool) -> Self {
+        self.fragment_passthrough = fragment_passthrough;
+        self
+    }
+
+    /// Layers personal and group links over the global ones. Defaults to `false`.
+    ///
+    /// When enabled, a request with a [`User`](crate::User) in its
+    /// [`RequestContext`] looks for the user's personal link (stored as
+    /// `~login/shortlink`) first, then for a link of each of the user's groups in order
+    /// (`@group/shortlink`), then for the global link. Requests can also name one of
+    /// their own layers directly, as in `go/~alice/notes`; naming anyone else's fails
+    /// with [`GolinkError::Forbidden`]. See [`LinkLayer`].
+    #[must_use]
+    pub fn layered_links(mut self, layered_links: bool) -> Self {
+        self.layered_links = layered_links;
+        self
+    }
+
+    /// Retries a shortlink that isn't found without its trailing punctuation and
+    /// stray brackets, so a `go/docs).` pasted from a sentence still resolves.
+    /// Defaults to `true`.
+    ///
+    /// Trailing punctuation after the shortlink, as in `go/docs/api).`, is removed from
+    /// the rest of the path too, rather than expanded into the URL. A resolution found
+    /// this way reports the punctuation it removed in its `trimmed` field.
+    #[must_use]
+    pub fn trim_punctuation(mut self, trim_punctuation: bool) -> Self {
+        self.trim_punctuation = trim_punctuation;
+        self
+    }
+
+    /// Suggests up to `suggestions` similar shortlinks when a request isn't found, like
+    /// `standup` for `go/standpu`. Defaults to 0, which turns suggestions off.
+    ///
+    /// Suggestions come from [`LinkStore::shortlinks`] (or its async counterpart) and
+    /// are returned in [`GolinkError::NotFound`]. Shortlinks within a few typos of the
+    /// request, or that it's a prefix of, are suggested, closest and then most popular
+    /// first. Links the requester may not resolve, and unlisted links, are never
+    /// suggested.
+    #[must_use]
+    pub fn suggestions(mut self, suggestions: usize) -> Self {
+        self.suggestions = suggestions;
+        self
+    }
+
+    /// Sets what happens to requests for shortlinks that aren't stored. Defaults to
+    /// [`FallbackPolicy::NotFound`], which fails with [`GolinkError::NotFound`].
+    #[must_use]
+    pub fn fallback(mut self, fallback: FallbackPolicy) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Sets the rules expanded URLs must follow, rejecting targets like
+    /// `javascript:alert(1)` with [`GolinkError::UnsafeTarget`]. Defaults to
+    /// [`SafetyPolicy::default()`]; use [`SafetyPolicy::permissive()`] to turn the
+    /// checks off.
+    #[must_use]
+    pub fn safety_policy(mut self, safety_policy: SafetyPolicy) -> Self {
+        self.safety_policy = safety_policy;
+        self
+    }
+
+    /// Sets how many compiled long URLs the resolver keeps, so that popular links are
+    /// only parsed once. Defaults to 1024; 0 compiles every link on every request.
+    ///
+    /// The cache is shared by clones of the resolver; when it's full, the least recently
+    /// used long URL is evicted.
+    #[must_use]
+    pub fn compiled_link_capacity(mut self, compiled_link_capacity: usize) -> Self {
+        self.compiled_link_capacity = compiled_link_capacity;
+        self
+    }
+
+    /// Builds the resolver.
+    #[must_use]
+    pub fn build(self) -> Resolver {
+        Resolver {
+            normalizer: self.normalizer,
+            metadata_suffix: self.metadata_suffix,
+            path_append: self.path_append,
+            expander: self.expander,
+            max_input_length: self.max_input_length,
+            max_alias_depth: self.max_alias_depth,
+            max_shortlink_segments: self.max_shortlink_segments,
+            query_passthrough: self.query_passthrough,
+            fragment_passthrough: self.fragment_passthrough,
+            layered_links: self.layered_links,
+            trim_punctuation: self.trim_punctuation,
+            suggestions: self.suggestions,
+            fallback: self.fallback,
+            safety_policy: self.safety_policy,
+            compiled: Arc::new(CompiledCache::new(self.compiled_link_capacity)),
+        }
+    }
+}
+
+/// A link found by [`Resolver::lookup_longest`].
+struct Found {
+    layer: LinkLayer,
+    shortlink: String,
+    link: Link,
+    /// The number of request segments consumed by the shortlink and any namespace.
+    consumed: usize,
+}
+
+struct ParsedInput {
+    segments: Vec<String>,
+    query: Option<String>,
+    fragment: Option<String>,
+    is_metadata_request: bool,
+}
+
+impl Resolver {
+    /// Creates a builder for configuring a resolver.
+    #[must_use]
+    pub fn builder() -> ResolverBuilder {
+        ResolverBuilder::new()
+    }
+
+    /// Normalizes a shortlink with this resolver's [`Normalizer`].
+    ///
+    /// Use this when storing new shortlinks so that they match what this resolver
+    /// passes to your lookup function. Keeps as many path segments as the resolver's
+    /// [`max_shortlink_segments`](ResolverBuilder::max_shortlink_segments) allows.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidInput`: A segment's escapes aren't valid UTF-8, as for a request
+    pub fn normalize_shortlink(&self, input: &str) -> Result<String, GolinkError> {
+        self.normalizer
+            .normalize_segments(input, self.max_shortlink_segments.max(1))
+    }
+
+    /// Compiles a link for this resolver, parsing its long URL once so it can be
+    /// expanded for many requests.
+    ///
+    /// The resolver compiles (and caches) links itself while resolving; call this to
+    /// check a link up front or to see how its long URL will be treated.
+    ///
+    /// # Errors
+    ///
+    /// - `TemplateError`: The link's signature is invalid, or its long URL is a template
+    ///   with invalid syntax
+    pub fn compile(&self, link: Link) -> Result<CompiledLink, GolinkError> {
+        let url = self.compiled.get_or_compile(&link, || {
+            CompiledUrl::compile(
+                &link.url,
+                link.kind,
+                link.signature.as_deref(),
+                self.expander.as_ref(),
+            )
+        })?;
+        Ok(CompiledLink::new(link, url))
+    }
+
+    /// Checks a link before it is stored, returning how this resolver will treat its
+    /// long URL: [`LinkKind::Template`] if it will be rendered, or [`LinkKind::Literal`]
+    /// if it will be used as-is.
+    ///
+    /// See [`validate_link()`](crate::validate_link) for details.
+    ///
+    /// # Errors
+    ///
+    /// - `TemplateError`: The link's signature is invalid, or its long URL is a template
+    ///   with invalid syntax, an unknown variable or an unknown formatter
+    pub fn validate_link(&self, link: &Link) -> Result<LinkKind, GolinkError> {
+        let url = CompiledUrl::compile(
+            &link.url,
+            link.kind,
+            link.signature.as_deref(),
+            self.expander.as_ref(),
+        )?;
+        url.validate()?;
+        Ok(url.kind())
+    }
+
+    /// Resolves a short URL to its expanded form using a synchronous [`LinkStore`].
+    ///
+    /// See [`resolve()`](crate::resolve) for details.
+    ///
+    /// # Errors
+    ///
+    /// See [`resolve()`](crate::resolve).
+    pub fn resolve<S>(&self, input: &str, store: &S) -> Result<GolinkResolution, GolinkError>
+    where
+        S: LinkStore + ?Sized,
+    {
+        self.resolve_with_context(input, &RequestContext::default(), store)
+    }
+
+    /// Resolves a short URL on behalf of the request described by `context`, using a
+    /// synchronous [`LinkStore`].
+    ///
+    /// Templates can refer to the requesting user as `user`, and the store receives the
+    /// context through [`LinkStore::lookup_with_context`].
+    ///
+    /// # Errors
+    ///
+    /// See [`resolve()`](crate::resolve).
+    pub fn resolve_with_context<S>(
+        &self,
+        input: &str,
+        context: &RequestContext,
+        store: &S,
+    ) -> Result<GolinkResolution, GolinkError>
+    where
+        S: LinkStore + ?Sized,
+    {
+        complete(self.resolve_with(input, context, &Blocking(store)))
+    }
+
+    /// Resolves a short URL to its expanded form using an [`AsyncLinkStore`].
+    ///
+    /// See [`resolve_async()`](crate::resolve_async) for details.
+    ///
+    /// # Errors
+    ///
+    /// See [`resolve()`](crate::resolve).
+    pub async fn resolve_async<S>(
+        &self,
+        input: &str,
+        store: &S,
+    ) -> Result<GolinkResolution, GolinkError>
+    where
+        S: AsyncLinkStore + ?Sized,
+    {
+        self.resolve_async_with_context(input, &RequestContext::default(), store)
+            .await
+    }
+
+    /// Resolves a short URL on behalf of the request described by `context`, using an
+    /// [`AsyncLinkStore`].
+    ///
+    /// See [`resolve_with_context()`](Self::resolve_with_context) for details.
+    ///
+    /// # Errors
+    ///
+    /// See [`resolve()`](crate::resolve).
+    pub async fn resolve_async_with_context<S>(
+        &self,
+        input: &str,
+        context: &RequestContext,
+        store: &S,
+    ) -> Result<GolinkResolution, GolinkError>
+    where
+        S: AsyncLinkStore + ?Sized,
+    {
+        self.resolve_with(input, context, store).await
+    }
+
+    /// The resolution pipeline shared by the synchronous and asynchronous entry points.
+    async fn resolve_with<S>(
+        &self,
+        input: &str,
+        context: &RequestContext,
+        store: &S,
+    ) -> Result<GolinkResolution, GolinkError>
+    where
+        S: AsyncLinkStore + ?Sized,
+    {
+        let mut parsed = self.parse_input(input)?;
+        let mut trimmed = None;
+        let found = match self.lookup_longest(store, context, &parsed.segments).await {
+            Err(GolinkError::NotFound { shortlink: key, .. }) => {
+                let Some((retry, punctuation)) = self
+                    .trim_trailing_punctuation(&parsed)
+                    .filter(|_| self.trim_punctuation)
+                else {
+                    return self.fall_back(store, context, &parsed, key).await;
+                };
+                match self.lookup_longest(store, context, &retry.segments).await {
+                    // Report the key as requested, not the one we guessed at
+                    Err(GolinkError::NotFound { .. }) => {
+                        return self.fall_back(store, context, &parsed, key).await;
+                    }
+                    found => {
+                        parsed = retry;
+                        trimmed = Some(punctuation);
+                        found?
+                    }
+                }
+            }
+            found => found?,
+        };
+        let Found {
+            layer,
+            shortlink,
+            mut link,
+            consumed,
+        } = found;
+        // Punctuation after a link's parameters, as in `go/docs/api).`, is never part of them
+        if self.trim_punctuation
+            && trimmed.is_none()
+            && consumed < parsed.segments.len()
+            && let Some((retry, punctuation)) = self.trim_trailing_punctuation(&parsed)
+        {
+            parsed = retry;
+            trimmed = Some(punctuation);
+        }
+        let user = context.user.as_ref();
+        if !link.is_accessible_by(user) {
+            return Err(GolinkError::Forbidden(shortlink));
+        }
+
+        if parsed.is_metadata_request {
+            return Ok(GolinkResolution::MetadataRequest {
+                shortlink,
+                link,
+                layer,
+                trimmed,
+            });
+        }
+
+        let mut segments = parsed.segments[consumed..].to_vec();
+        let mut chain = vec![layer.key(&shortlink)];
+        let follow_aliases = self.max_alias_depth > 0;
+        while let Some(target) = link.alias_target().filter(|_| follow_aliases) {
+            let target_segments = target
+                .split('/')
+                .map(str::to_string)
+                .chain(segments)
+                .collect_vec();
+            // Report the requested shortlink, so the error doesn't reveal the alias target
+            let target = match self.lookup_longest(store, context, &target_segments).await {
+                Ok(target) => target,
+                Err(GolinkError::NotFound { .. }) => {
+                    return Err(GolinkError::BrokenAlias(shortlink));
+                }
+                Err(GolinkError::Forbidden(_)) => return Err(GolinkError::Forbidden(shortlink)),
+                Err(error) => return Err(error),
+            };
+
+            let key = target.layer.key(&target.shortlink);
+            let is_cycle = chain.contains(&key);
+            chain.push(key);
+            if is_cycle || chain.len() > self.max_alias_depth + 1 {
+                return Err(GolinkError::AliasCycle(chain));
+            }
+
+            if !target.link.is_accessible_by(user) {
+                return Err(GolinkError::Forbidden(shortlink));
+            }
+
+            link = target.link;
+            segments = target_segments[target.consumed..].to_vec();
+        }
+
+        let (url, compiled) = self.expand(link, &shortlink, &segments, &parsed, context)?;
+        Ok(GolinkResolution::RedirectRequest {
+            url,
+            shortlink,
+            link: compiled.into_link(),
+            layer,
+            trimmed,
+        })
+    }
+
+    /// Expands a link for the rest of a request, applying the query, fragment and
+    /// safety policies.
+    fn expand(
+        &self,
+        link: Link,
+        shortlink: &str,
+        segments: &[String],
+        parsed: &ParsedInput,
+        context: &RequestContext,
+    ) -> Result<(String, CompiledLink), GolinkError> {
+        let compiled = self.compile(link)?;
+        let environment = compiled
+            .url()
+            .environment(segments)?
+            .with_shortlink(shortlink)
+            .with_query(parsed.query.as_deref())
+            .with_context(context);
+        let expansion = pass_through(
+            compiled.url().expand(&environment, self.path_append)?,
+            parsed.query.as_deref(),
+            parsed
+                .fragment
+                .as_deref()
+                .filter(|_| self.fragment_passthrough),
+            self.query_passthrough,
+        );
+        self.check_safety(&expansion, shortlink)?;
+        Ok((expansion, compiled))
+    }
+
+    fn check_safety(&self, url: &str, shortlink: &str) -> Result<(), GolinkError> {
+        self.safety_policy
+            .check(url)
+            .map_err(|reason| GolinkError::UnsafeTarget {
+                shortlink: shortlink.to_string(),
+                reason,
+            })
+    }
+
+    /// Applies the fallback policy to a request whose shortlink isn't stored.
+    async fn fall_back<S>(
+        &self,
+        store: &S,
+        context: &RequestContext,
+        parsed: &ParsedInput,
+        shortlink: String,
+    ) -> Result<GolinkResolution, GolinkError>
+    where
+        S: AsyncLinkStore + ?Sized,
+    {
+        let template = match &self.fallback {
+            _ if parsed.is_metadata_request => {
+                return Err(self.not_found(store, context, shortlink).await);
+            }
+            FallbackPolicy::NotFound => {
+                return Err(self.not_found(store, context, shortlink).await);
+            }
+            FallbackPolicy::Search(template) | FallbackPolicy::Create(template) => template,
+            FallbackPolicy::Upstream(base) => {
+                let url = upstream_url(base, &parsed.segments, parsed.query.as_deref());
+                self.check_safety(&url, &shortlink)?;
+                return Ok(GolinkResolution::Fallback { url, shortlink });
+            }
+        };
+
+        // The resolver's engine decides whether the URL is a template, as for a link's
+        let link = Link::new(template.as_str());
+        let remainder = if self.compile(link.clone())?.kind() == LinkKind::Template {
+            // The shortlink covers one segment, plus its namespace if it has one
+            let consumed = shortlink.split('/').count();
+            parsed.segments.get(consumed..).unwrap_or_default()
+        } else {
+            // A literal URL only learns what was missing from the path appended to it
+            &parsed.segments
+        };
+        let (url, _) = self.expand(link, &shortlink, remainder, parsed, context)?;
+        Ok(GolinkResolution::Fallback { url, shortlink })
+    }
+
+    /// The layers to search, in order, for a request without an explicit namespace.
+    fn layers(&self, context: &RequestContext) -> Vec<LinkLayer> {
+        let mut layers = Vec::new();
+        if self.layered_links
+            && let Some(user) = &context.user
+        {
+            if let Ok(login) = self.normalizer.normalize_segment(&user.login)
+                && !login.is_empty()
+            {
+                layers.push(LinkLayer::Personal(login));
+            }
+            layers.extend(
+                user.groups
+                    .iter()
+                    .filter_map(|group| self.normalizer.normalize_segment(group).ok())
+                    .filter(|group| !group.is_empty())
+                    .map(LinkLayer::Group),
+            );
+        }
+        layers.push(LinkLayer::Global);
+        layers
+    }
+
+    /// Looks up the longest shortlink that `segments` starts with, searching each layer
+    /// in turn.
+    ///
+    /// With layered links enabled, a leading namespace segment like `~alice` restricts
+    /// the search to that layer, which only its user or group members may search.
+    async fn lookup_longest<S>(
+        &self,
+        store: &S,
+        context: &RequestContext,
+        segments: &[String],
+    ) -> Result<Found, GolinkError>
+    where
+        S: AsyncLinkStore + ?Sized,
+    {
+        let namespace = segments
+            .first()
+            .filter(|_| self.layered_links)
+            .map(|segment| LinkLayer::from_namespace(segment, &self.normalizer))
+            .transpose()?
+            .flatten();
+        let (layers, offset) = match namespace {
+            Some(layer) => (vec![layer], 1),
+            None => (self.layers(context), 0),
+        };
+
+        let keys = segments[offset..]
+            .iter()
+            .take(self.max_shortlink_segments.max(1))
+            .map(|segment| self.normalizer.normalize_segment(segment))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .take_while(|segment| !segment.is_empty())
+            .scan(String::new(), |key, segment| {
+                if !key.is_empty() {
+                    key.push('/');
+                }
+                key.push_str(&segment);
+                Some(key.clone())
+            })
+            .collect_vec();
+        if keys.is_empty() {
+            return Err(GolinkError::InvalidInput);
+        }
+        // Personal and group links are only reachable by their user or group members
+        if let Some(layer) = layers.first()
+            && offset == 1
+            && !self.layers(context).contains(layer)
+        {
+            return Err(GolinkError::Forbidden(layer.key(&keys[0])));
+        }
+
+        for layer in &layers {
+            for (index, key) in keys.iter().enumerate().rev() {
+                let link = store
+                    .lookup_with_context(&layer.key(key), context)
+                    .await
+                    .map_err(|error| GolinkError::LookupFailed(LookupError::new(error)))?;
+                if let Some(link) = link {
+                    return Ok(Found {
+                        layer: layer.clone(),
+                        shortlink: key.clone(),
+                        link,
+                        consumed: offset + index + 1,
+                    });
+                }
+            }
+        }
+
+        let last = layers.last().unwrap_or(&LinkLayer::Global);
+        Err(GolinkError::NotFound {
+            shortlink: last.key(&keys[0]),
+            suggestions: Vec::new(),
+        })
+    }
+
+    /// Builds the error for a requested shortlink that isn't stored, suggesting similar
+    /// ones the requester may resolve from the store if enabled.
+    async fn not_found<S>(
+        &self,
+        store: &S,
+        context: &RequestContext,
+        shortlink: String,
+    ) -> GolinkError
+    where
+        S: AsyncLinkStore + ?Sized,
+    {
+        if self.suggestions == 0 {
+            return GolinkError::NotFound {
+                shortlink,
+                suggestions: Vec::new(),
+            };
+        }
+        match store.shortlinks().await {
+            Ok(candidates) => GolinkError::NotFound {
+                suggestions: suggest(
+                    &shortlink,
+                    candidates,
+                    self.suggestions,
+                    context.user.as_ref(),
+                ),
+                shortlink,
+            },
+            Err(error) => GolinkError::LookupFailed(LookupError::new(error)),
+        }
+    }
+
+    fn parse_input(&self, input: &str) -> Result<ParsedInput, GolinkError> {
+        if input.len() > self.max_input_length {
+            return Err(GolinkError::InvalidInput);
+        }
+
+        let url = Url::parse(input).or_else(|_| Url::parse("https://go/")?.join(input))?;
+        let mut segments = url
+            .path_segments()
+            .ok_or(GolinkError::InvalidInput)?
+            .map(str::to_string)
+            .collect_vec();
+
+        let is_metadata_request =
+            !self.metadata_suffix.is_empty() && url.path().ends_with(&self.metadata_suffix);
+        if is_metadata_request && let Some(last) = segments.last_mut() {
+            *last = last.trim_end_matches(&self.metadata_suffix).to_string();
+        }
+
+        let first = segments.first().ok_or(GolinkError::InvalidInput)?;
+        if self.normalizer.normalize_segment(first)?.is_empty() {
+            return Err(GolinkError::InvalidInput);
+        }
+
+        Ok(ParsedInput {
+            segments,
+            query: url.query().map(str::to_string),
+            fragment: url.fragment().map(str::to_string),
+            is_metadata_request,
+        })
+    }
+
+    /// Removes the trailing punctuation from a request's last path segment, returning
+    /// the request as it would have been typed without it, and the punctuation removed.
+    fn trim_trailing_punctuation(&self, parsed: &ParsedInput) -> Option<(ParsedInput, String)> {
+        let last = parsed.segments.last()?;
+        let mut kept = last.as_str();
+        loop {
+            if let Some(rest) = kept.strip_suffix(TRAILING_PUNCTUATION) {
+                kept = rest;
+            } else if let Some((rest, escape)) = kept.split_at_checked(kept.len().saturating_sub(3))
+                && escape.starts_with('%')
+                && percent_decode_str(escape)
+                    .decode_utf8()
+                    .is_ok_and(|c| c.chars().all(|c| TRAILING_PUNCTUATION.contains(&c)))
+            {
+                kept = rest;
+            } else {
+                break;
+            }
+        }
+        if kept.len() == last.len() {
+            return None;
+        }
+
+        let mut segments = parsed.segments.clone();
+        let punctuation = percent_decode_str(&last[kept.len()..])
+            .decode_utf8_lossy()
+            .into_owned();
+        let mut is_metadata_request = parsed.is_metadata_request;
+        let mut kept = kept.to_string();
+        // A pasted `go/docs+.` is still a metadata request
+        if !is_metadata_request
+            && !self.metadata_suffix.is_empty()
+            && let Some(rest) = kept.strip_suffix(&self.metadata_suffix)
+        {
+            kept = rest.to_string();
+            is_metadata_request = true;
+        }
+        if kept.is_empty() && segments.len() > 1 {
+            // `go/docs/).` drops the segment rather than leaving an empty one behind
+            segments.pop();
+        } else if self
+            .normalizer
+            .normalize_segment(&kept)
+            .ok()
+            .is_none_or(|kept| kept.is_empty())
+        {
+            // Input that's nothing but punctuation isn't worth a retry
+            return None;
+        } else {
+            *segments.last_mut()? = kept;
+        }
+        Some((
+            ParsedInput {
+                segments,
+                query: parsed.query.clone(),
+                fragment: parsed.fragment.clone(),
+                is_metadata_request,
+            },
+            punctuation,
+        ))
+    }
+}
+
+/// Drives a future that is known to complete without suspending.
+///
+/// The synchronous entry points feed the shared pipeline a [`Blocking`] store whose
+/// lookups are already resolved, so a single poll always runs it to completion.
+fn complete<F: Future>(future: F) -> F::Output {
+    let mut cx = Context::from_waker(Waker::noop());
+    match pin!(future).poll(&mut cx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("synchronous resolution never suspends"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Access, CharClass, User, Visibility};
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+
+    fn lookup(input: &str) -> Option<String> {
+        match input {
+            "test" => Some("http://example.com/".to_string()),
+            "abcd" => Some("efgh".to_string()),
+            "Test" => Some("http://example.com/upper".to_string()),
+            "tpl" => Some("{ path }".to_string()),
+            "old" => Some("go/test".to_string()),
+            "api" => Some("alias:old/api".to_string()),
+            "loopa" => Some("go/loopb".to_string()),
+            "loopb" => Some("go/Loop-A".to_string()),
+            "dangling" => Some("go/missing".to_string()),
+            "team" => Some("https://team.example.com/".to_string()),
+            "team/oncall" => Some("https://oncall.example.com/".to_string()),
+            "oncall" => Some("go/team/oncall".to_string()),
+            _ => None,
+        }
+    }
+
+    fn link(shortlink: &str) -> Link {
+        lookup(shortlink).unwrap().into()
+    }
+
+    #[test]
+    fn it_uses_a_custom_metadata_suffix() {
+        let resolver = Resolver::builder().metadata_suffix("!").build();
+        assert_eq!(
+            resolver.resolve("/te-st!", &lookup),
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            resolver.resolve("/test+", &lookup),
+            Err(GolinkError::NotFound {
+                shortlink: "test+".to_string(),
+                suggestions: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn it_disables_metadata_requests_with_an_empty_suffix() {
+        let resolver = Resolver::builder().metadata_suffix("").build();
+        assert_eq!(
+            resolver.resolve("/test+", &lookup),
+            Err(GolinkError::NotFound {
+                shortlink: "test+".to_string(),
+                suggestions: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn it_discards_remaining_path_segments() {
+        let resolver = Resolver::builder().path_append(PathAppend::Discard).build();
+        assert_eq!(
+            resolver.resolve("/test/a/b", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "http://example.com/".to_string(),
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            resolver.resolve("/abcd/a/b", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "efgh".to_string(),
+                shortlink: "abcd".to_string(),
+                link: link("abcd"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_does_not_render_templates_when_disabled() {
+        let resolver = Resolver::builder()
+            .template_engine(TemplateEngine::Disabled)
+            .build();
+        assert_eq!(
+            resolver.resolve("/tpl/a", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "{ path }/a".to_string(),
+                shortlink: "tpl".to_string(),
+                link: link("tpl"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_rejects_inputs_over_the_length_limit() {
+        let resolver = Resolver::builder().max_input_length(8).build();
+        assert_eq!(
+            resolver.resolve("/test/abcdef", &lookup),
+            Err(GolinkError::InvalidInput)
+        );
+        assert!(resolver.resolve("/test/a", &lookup).is_ok());
+    }
+
+    #[test]
+    fn it_uses_the_configured_normalizer() {
+        let resolver = Resolver::builder()
+            .normalizer(Normalizer::new().case_sensitive(true))
+            .build();
+        assert_eq!(
+            resolver.resolve("/Te-st", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "http://example.com/upper".to_string(),
+                shortlink: "Test".to_string(),
+                link: link("Test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            resolver.normalize_shortlink("My-Service/docs"),
+            Ok("MyService".to_string())
+        );
+
+        let resolver = Resolver::builder()
+            .normalizer(Normalizer::new().strip(CharClass::NonAlphanumeric))
+            .layered_links(true)
+            .build();
+        let store =
+            |shortlink: &str| (shortlink == "~alice/foobar").then_some("https://example.com/");
+        let alice = RequestContext::new().with_user(User::new("alice"));
+        assert!(matches!(
+            resolver.resolve_with_context("/~Alice/foo_bar!", &alice, &store),
+            Ok(GolinkResolution::RedirectRequest { shortlink, .. }) if shortlink == "foobar"
+        ));
+        assert_eq!(
+            resolver.normalize_shortlink("Foo_Bar.baz"),
+            Ok("foobarbaz".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn async_it_shares_configuration_with_sync() {
+        let resolver = Resolver::builder().metadata_suffix("!").build();
+        let computed = resolver
+            .resolve_async("/test!", &|input: &str| {
+                let input = input.to_string();
+                async move { lookup(&input) }
+            })
+            .await;
+        assert_eq!(
+            computed,
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_follows_aliases_carrying_the_remainder() {
+        assert_eq!(
+            Resolver::default().resolve("/old/a", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "http://example.com/a".to_string(),
+                shortlink: "old".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            Resolver::default().resolve("/api/v1", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "http://example.com/api/v1".to_string(),
+                shortlink: "api".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_returns_the_alias_itself_for_metadata_requests() {
+        assert_eq!(
+            Resolver::default().resolve("/old+", &lookup),
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "old".to_string(),
+                link: link("old"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_detects_alias_cycles() {
+        assert_eq!(
+            Resolver::default().resolve("/loopa", &lookup),
+            Err(GolinkError::AliasCycle(vec![
+                "loopa".to_string(),
+                "loopb".to_string(),
+                "loopa".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn it_limits_alias_depth() {
+        let resolver = Resolver::builder().max_alias_depth(1).build();
+        assert!(resolver.resolve("/old", &lookup).is_ok());
+        assert_eq!(
+            resolver.resolve("/api", &lookup),
+            Err(GolinkError::AliasCycle(vec![
+                "api".to_string(),
+                "old".to_string(),
+                "test".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn it_treats_aliases_as_literals_when_disabled() {
+        let resolver = Resolver::builder().max_alias_depth(0).build();
+        assert_eq!(
+            resolver.resolve("/old", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "go/test".to_string(),
+                shortlink: "old".to_string(),
+                link: link("old"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_reports_broken_aliases_without_their_target() {
+        assert_eq!(
+            Resolver::default().resolve("/dangling", &lookup),
+            Err(GolinkError::BrokenAlias("dangling".to_string()))
+        );
+        // Even with a fallback for unknown shortlinks
+        let resolver = Resolver::builder()
+            .fallback(FallbackPolicy::Search(
+                "https://search.example.com/?q={ shortlink }".to_string(),
+            ))
+            .build();
+        assert_eq!(
+            resolver.resolve("/dangling/a", &lookup),
+            Err(GolinkError::BrokenAlias("dangling".to_string()))
+        );
+
+        // Aliases into another user's links don't reveal their target either
+        let links = HashMap::from([
+            ("~alice/notes", "https://notes.example.com/alice"),
+            ("notes", "go/~alice/notes"),
+        ]);
+        let store = |shortlink: &str| links.get(shortlink).copied();
+        let bob = RequestContext::new().with_user(User::new("bob"));
+        assert_eq!(
+            Resolver::builder()
+                .layered_links(true)
+                .build()
+                .resolve_with_context("/notes", &bob, &store),
+            Err(GolinkError::Forbidden("notes".to_string()))
+        );
+    }
+
+    #[test]
+    fn it_matches_the_longest_hierarchical_shortlink() {
+        let resolver = Resolver::builder().max_shortlink_segments(2).build();
+        assert_eq!(
+            resolver.resolve("/Team/On-Call/today", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "https://oncall.example.com/today".to_string(),
+                shortlink: "team/oncall".to_string(),
+                link: link("team/oncall"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            resolver.resolve("/team/roadmap", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "https://team.example.com/roadmap".to_string(),
+                shortlink: "team".to_string(),
+                link: link("team"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            resolver.resolve("/team/oncall+", &lookup),
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "team/oncall".to_string(),
+                link: link("team/oncall"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            resolver.normalize_shortlink("Team/On-Call/today"),
+            Ok("team/oncall".to_string())
+        );
+    }
+
+    #[test]
+    fn it_only_matches_the_first_segment_by_default() {
+        assert_eq!(
+            Resolver::default().resolve("/team/oncall", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "https://team.example.com/oncall".to_string(),
+                shortlink: "team".to_string(),
+                link: link("team"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_matches_hierarchical_alias_targets() {
+        let resolver = Resolver::builder().max_shortlink_segments(2).build();
+        assert_eq!(
+            resolver.resolve("/oncall/today", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "https://oncall.example.com/today".to_string(),
+                shortlink: "oncall".to_string(),
+                link: link("team/oncall"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_binds_signature_parameters_after_aliases() {
+        let gh = Link::new("https://github.com/{ org }/{ repo }").with_signature("gh/{org}/{repo}");
+        let store = |shortlink: &str| match shortlink {
+            "gh" => Some(gh.clone()),
+            "rust" => Some(Link::new("go/gh/rust-lang")),
+            _ => None,
+        };
+        let resolver = Resolver::default();
+
+        assert_eq!(
+            resolver.resolve("/rust/cargo", &store),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "https://github.com/rust-lang/cargo".to_string(),
+                shortlink: "rust".to_string(),
+                link: gh.clone(),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            resolver.resolve("/gh/rust-lang", &store),
+            Err(GolinkError::MissingParameters {
+                signature: "gh/{org}/{repo}".to_string(),
+                missing: vec!["repo".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn it_passes_the_query_string_and_fragment_through() {
+        let resolver = Resolver::builder()
+            .query_passthrough(QueryPassthrough::KeepTarget)
+            .fragment_passthrough(true)
+            .build();
+        assert_eq!(
+            resolver.resolve("/test/a?q=rust#usage", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "http://example.com/a?q=rust#usage".to_string(),
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            Resolver::default().resolve("/test/a?q=rust#usage", &lookup),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "http://example.com/a".to_string(),
+                shortlink: "test".to_string(),
+                link: link("test"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_exposes_the_query_string_to_templates() {
+        let store = |_: &str| Some("https://example.com/search?q={ query.q }");
+        assert_eq!(
+            Resolver::default().resolve("/search?q=rust+lang", &store),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "https://example.com/search?q=rust%20lang".to_string(),
+                shortlink: "search".to_string(),
+                link: Link::new("https://example.com/search?q={ query.q }"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_exposes_the_requesting_user_to_templates() {
+        let context = RequestContext::new().with_user(
+            User::new("jameslittle230")
+                .with_display_name("James")
+                .with_attribute("team", "search"),
+        );
+        let store = |shortlink: &str| match shortlink {
+            "me" => Some("https://profiles.example.com/{ user.login }"),
+            _ => {
+                Some("https://teams.example.com/{ user.attributes.team }?as={ user.display_name }")
+            }
+        };
+        let url = |input, context| match Resolver::default()
+            .resolve_with_context(input, context, &store)
+        {
+            Ok(GolinkResolution::RedirectRequest { url, .. }) => Ok(url),
+            Ok(other) => panic!("unexpected resolution {other:?}"),
+            Err(error) => Err(error),
+        };
+
+        assert_eq!(
+            url("/me", &context),
+            Ok("https://profiles.example.com/jameslittle230".to_string())
+        );
+        assert_eq!(
+            url("/team", &context),
+            Ok("https://teams.example.com/search?as=James".to_string())
+        );
+        assert!(matches!(
+            url("/me", &RequestContext::new()),
+            Err(GolinkError::TemplateError(_))
+        ));
+    }
+
+    #[test]
+    fn it_passes_the_request_context_to_the_store() {
+        struct Personal;
+
+        impl LinkStore for Personal {
+            type Error = std::convert::Infallible;
+
+            fn lookup(&self, _: &str) -> Result<Option<Link>, Self::Error> {
+                Ok(Some(Link::new("https://example.com/shared")))
+            }
+
+            fn lookup_with_context(
+                &self,
+                shortlink: &str,
+                context: &RequestContext,
+            ) -> Result<Option<Link>, Self::Error> {
+                match &context.user {
+                    Some(user) if user.in_group("Eng") => Ok(Some(Link::new(format!(
+                        "https://example.com/{}/{shortlink}",
+                        user.login
+                    )))),
+                    _ => self.lookup(shortlink),
+                }
+            }
+        }
+
+        let context =
+            RequestContext::new().with_user(User::new("jameslittle230").with_groups(["eng"]));
+        assert_eq!(
+            Resolver::default().resolve_with_context("/notes", &context, &Personal),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "https://example.com/jameslittle230/notes".to_string(),
+                shortlink: "notes".to_string(),
+                link: Link::new("https://example.com/jameslittle230/notes"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            Resolver::default().resolve("/notes", &Personal),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "https://example.com/shared".to_string(),
+                shortlink: "notes".to_string(),
+                link: Link::new("https://example.com/shared"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_checks_personal_and_group_links_first() {
+        let links = HashMap::from([
+            ("standup", "https://meet.example.com/all-hands"),
+            ("@eng/standup", "https://meet.example.com/eng"),
+            ("~alice/standup", "https://meet.example.com/alice"),
+            ("~alice/notes", "https://notes.example.com/alice"),
+            ("~alice/mine", "go/standup"),
+        ]);
+        let store = |shortlink: &str| links.get(shortlink).map(|url| (*url).to_string());
+        let resolver = Resolver::builder().layered_links(true).build();
+        let resolve = |input, user: Option<User>| {
+            let context = RequestContext {
+                user: user.map(|user| user.with_groups(["Eng"])),
+            };
+            resolver.resolve_with_context(input, &context, &store).map(
+                |resolution| match resolution {
+                    GolinkResolution::RedirectRequest { url, layer, .. } => (url, layer),
+                    other => panic!("unexpected resolution {other:?}"),
+                },
+            )
+        };
+
+        let personal = LinkLayer::Personal("alice".to_string());
+        let group = LinkLayer::Group("eng".to_string());
+        assert_eq!(
+            resolve("/standup", Some(User::new("Alice"))),
+            Ok((
+                "https://meet.example.com/alice".to_string(),
+                personal.clone()
+            ))
+        );
+        assert_eq!(
+            resolve("/standup", Some(User::new("bob"))),
+            Ok(("https://meet.example.com/eng".to_string(), group))
+        );
+        assert_eq!(
+            resolve("/standup", None),
+            Ok((
+                "https://meet.example.com/all-hands".to_string(),
+                LinkLayer::Global
+            ))
+        );
+        assert_eq!(
+            resolve("/mine", Some(User::new("alice"))),
+            Ok((
+                "https://meet.example.com/alice".to_string(),
+                personal.clone()
+            ))
+        );
+        assert_eq!(
+            resolve("/~Alice/notes/today", Some(User::new("alice"))),
+            Ok((
+                "https://notes.example.com/alice/today".to_string(),
+                personal
+            ))
+        );
+        assert_eq!(
+            resolve("/@eng/standup", Some(User::new("bob"))),
+            Ok((
+                "https://meet.example.com/eng".to_string(),
+                LinkLayer::Group("eng".to_string())
+            ))
+        );
+        assert_eq!(
+            resolve("/~bob/notes", Some(User::new("bob"))),
+            Err(GolinkError::NotFound {
+                shortlink: "~bob/notes".to_string(),
+                suggestions: Vec::new(),
+            })
+        );
+        assert_eq!(resolve("/~alice", None), Err(GolinkError::InvalidInput));
+        assert_eq!(
+            resolve("/~al%FFice/notes", Some(User::new("alice"))),
+            Err(GolinkError::InvalidInput)
+        );
+        assert_eq!(
+            resolve("/~%61lice/notes", Some(User::new("alice"))),
+            Ok((
+                "https://notes.example.com/alice".to_string(),
+                LinkLayer::Personal("alice".to_string())
+            ))
+        );
+
+        // Other users' personal links and other groups' links stay private
+        let forbidden = |shortlink: &str| Err(GolinkError::Forbidden(shortlink.to_string()));
+        assert_eq!(
+            resolve("/~alice/notes/today", None),
+            forbidden("~alice/notes")
+        );
+        assert_eq!(
+            resolve("/~alice/standup", Some(User::new("bob"))),
+            forbidden("~alice/standup")
+        );
+        assert_eq!(resolve("/@eng/standup", None), forbidden("@eng/standup"));
+        assert_eq!(
+            resolve("/@hr/standup", Some(User::new("bob"))),
+            forbidden("@hr/standup")
+        );
+    }
+
+    #[test]
+    fn it_enforces_link_access() {
+        let links = HashMap::from([
+            (
+                "handbook",
+                Link::new("https://hr.example.com/handbook").with_access(Access::Authenticated),
+            ),
+            (
+                "salaries",
+                Link::new("https://hr.example.com/salaries")
+                    .with_access(Access::restricted(["carol"], ["hr"]))
+                    .with_owner("dave"),
+            ),
+            ("pay", Link::new("go/salaries")),
+            (
+                "team",
+                Link::new("https://team.example.com/")
+                    .with_access(Access::restricted(["émile"], ["équipe"])),
+            ),
+        ]);
+        let store = |shortlink: &str| links.get(shortlink).cloned();
+        let resolve = |input, user: Option<User>| {
+            let context = RequestContext { user };
+            Resolver::default()
+                .resolve_with_context(input, &context, &store)
+                .map(|resolution| resolution.link().unwrap().url.clone())
+        };
+        let forbidden = |shortlink: &str| Err(GolinkError::Forbidden(shortlink.to_string()));
+
+        assert_eq!(resolve("/handbook", None), forbidden("handbook"));
+        assert_eq!(
+            resolve("/handbook", Some(User::new("bob"))),
+            Ok("https://hr.example.com/handbook".to_string())
+        );
+
+        assert_eq!(
+            resolve("/salaries", Some(User::new("bob"))),
+            forbidden("salaries")
+        );
+        assert_eq!(resolve("/salaries+", None), forbidden("salaries"));
+        assert!(resolve("/salaries", Some(User::new("Carol"))).is_ok());
+        assert!(resolve("/salaries", Some(User::new("erin").with_groups(["hr"]))).is_ok());
+        assert!(resolve("/salaries", Some(User::new("erin").with_groups(["HR"]))).is_ok());
+        // Owners are free text and get no access of their own
+        assert_eq!(
+            resolve("/salaries", Some(User::new("dave"))),
+            forbidden("salaries")
+        );
+        // Logins and groups are compared ignoring case, including non-ASCII case
+        assert!(resolve("/salaries", Some(User::new("CAROL"))).is_ok());
+        assert!(resolve("/team", Some(User::new("ÉMILE"))).is_ok());
+        assert!(resolve("/team", Some(User::new("zoë").with_groups(["ÉQUIPE"]))).is_ok());
+        assert_eq!(resolve("/team", Some(User::new("bob"))), forbidden("team"));
+
+        assert_eq!(resolve("/pay", Some(User::new("bob"))), forbidden("pay"));
+        assert!(resolve("/pay", Some(User::new("carol"))).is_ok());
+    }
+
+    #[test]
+    fn it_ignores_layers_unless_enabled() {
+        let store = |shortlink: &str| match shortlink {
+            "~alice/standup" => Some("https://meet.example.com/alice"),
+            "standup" => Some("https://meet.example.com/all-hands"),
+            _ => None,
+        };
+        let context = RequestContext::new().with_user(User::new("alice"));
+        assert_eq!(
+            Resolver::default().resolve_with_context("/standup+", &context, &store),
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "standup".to_string(),
+                link: Link::new("https://meet.example.com/all-hands"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            Resolver::builder()
+                .layered_links(true)
+                .build()
+                .resolve_with_context("/standup+", &context, &store),
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "standup".to_string(),
+                link: Link::new("https://meet.example.com/alice"),
+                layer: LinkLayer::Personal("alice".to_string()),
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_percent_decodes_shortlinks() {
+        let store = |shortlink: &str| match shortlink {
+            "myservice" => Some("https://example.com/{ path }/{ raw_path | unescaped }"),
+            "über" => Some("https://example.com/uber"),
+            _ => None,
+        };
+        let url = |input| match Resolver::default().resolve(input, &store) {
+            Ok(GolinkResolution::RedirectRequest { url, .. }) => Ok(url),
+            Err(error) => Err(error),
+            other => panic!("unexpected resolution {other:?}"),
+        };
+
+        assert_eq!(
+            url("/my%2Dservice/a%2Fb"),
+            Ok("https://example.com/a/b/a%2Fb".to_string())
+        );
+        assert_eq!(
+            url("/%C3%9Cber"),
+            Ok("https://example.com/uber".to_string())
+        );
+        assert_eq!(url("/%FF"), Err(GolinkError::InvalidInput));
+        assert_eq!(url("/my%2Dservice%FF"), Err(GolinkError::InvalidInput));
+    }
+
+    #[test]
+    fn it_trims_pasted_punctuation() {
+        let store = |shortlink: &str| match shortlink {
+            "docs" => Some("https://docs.example.com/"),
+            "v1.2" => Some("https://example.com/releases/1.2"),
+            _ => None,
+        };
+        let resolve = |resolver: &Resolver, input| match resolver.resolve(input, &store) {
+            Ok(resolution) => Ok((resolution.trimmed().map(str::to_string), resolution)),
+            Err(error) => Err(error),
+        };
+        let url = |resolution| match resolution {
+            GolinkResolution::RedirectRequest { url, .. } => url,
+            resolution => panic!("expected a redirect, got {resolution:?}"),
+        };
+        let resolver = Resolver::default();
+
+        let (trimmed, resolution) = resolve(&resolver, "/docs),").unwrap();
+        assert_eq!(trimmed.as_deref(), Some("),"));
+        assert_eq!(resolution.shortlink(), "docs");
+        let (trimmed, _) = resolve(&resolver, "/docs%22.").unwrap();
+        assert_eq!(trimmed.as_deref(), Some("\"."));
+        let (trimmed, _) = resolve(&resolver, "/v1.2").unwrap();
+        assert_eq!(trimmed, None);
+        let (trimmed, resolution) = resolve(&resolver, "/docs+.").unwrap();
+        assert_eq!(trimmed.as_deref(), Some("."));
+        assert!(matches!(
+            resolution,
+            GolinkResolution::MetadataRequest { .. }
+        ));
+
+        // Punctuation after the shortlink is kept out of the expanded URL
+        let (trimmed, resolution) = resolve(&resolver, "/docs/api).").unwrap();
+        assert_eq!(trimmed.as_deref(), Some(")."));
+        assert_eq!(url(resolution), "https://docs.example.com/api");
+        let (trimmed, resolution) = resolve(&resolver, "/docs/api/v2%22,").unwrap();
+        assert_eq!(trimmed.as_deref(), Some("\","));
+        assert_eq!(url(resolution), "https://docs.example.com/api/v2");
+        let (trimmed, resolution) = resolve(&resolver, "/docs/).").unwrap();
+        assert_eq!(trimmed.as_deref(), Some(")."));
+        assert_eq!(url(resolution), "https://docs.example.com/");
+        let (trimmed, resolution) = resolve(&resolver, "/docs/api+.").unwrap();
+        assert_eq!(trimmed.as_deref(), Some("."));
+        assert!(matches!(
+            resolution,
+            GolinkResolution::MetadataRequest { .. }
+        ));
+        let untrimmed = Resolver::builder().trim_punctuation(false).build();
+        let (trimmed, resolution) = resolve(&untrimmed, "/docs/api).").unwrap();
+        assert_eq!(trimmed, None);
+        assert_eq!(url(resolution), "https://docs.example.com/api%29.");
+
+        assert_eq!(
+            resolve(&resolver, "/nope)."),
+            Err(GolinkError::NotFound {
+                shortlink: "nope).".to_string(),
+                suggestions: Vec::new(),
+            })
+        );
+        // Input that's only punctuation keeps the error it had before trimming
+        for input in ["/).", "/.", "/+."] {
+            assert_eq!(resolve(&resolver, input), resolve(&untrimmed, input));
+            assert!(resolve(&resolver, input).is_err());
+        }
+        let search = Resolver::builder()
+            .fallback(FallbackPolicy::Search(
+                "https://search.example.com/?q={ shortlink }".to_string(),
+            ))
+            .build();
+        assert!(matches!(
+            resolve(&search, "/)."),
+            Ok((None, GolinkResolution::Fallback { .. }))
+        ));
+        assert_eq!(
+            resolve(
+                &Resolver::builder().trim_punctuation(false).build(),
+                "/docs."
+            ),
+            Err(GolinkError::NotFound {
+                shortlink: "docs.".to_string(),
+                suggestions: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn it_suggests_similar_shortlinks() {
+        let store: HashMap<String, String> = ["standup", "standups", "docs"]
+            .into_iter()
+            .map(|shortlink| (shortlink.to_string(), "https://example.com/".to_string()))
+            .collect();
+        let not_found = |shortlink: &str, suggestions: &[&str]| {
+            Err(GolinkError::NotFound {
+                shortlink: shortlink.to_string(),
+                suggestions: suggestions.iter().map(ToString::to_string).collect(),
+            })
+        };
+
+        assert_eq!(
+            Resolver::default().resolve("/standpu", &store),
+            not_found("standpu", &[])
+        );
+        let resolver = Resolver::builder().suggestions(3).build();
+        assert_eq!(
+            resolver.resolve("/Stand-PU/a", &store),
+            not_found("standpu", &["standup", "standups"])
+        );
+        assert_eq!(resolver.resolve("/xyz", &store), not_found("xyz", &[]));
+        assert_eq!(
+            resolver.resolve("/doc).", &store),
+            not_found("doc).", &["docs"])
+        );
+        // Only links the requester may resolve, and that are listed, are suggested
+        let links = HashMap::from([
+            (
+                "standup".to_string(),
+                Link::new("https://example.com/")
+                    .with_access(Access::restricted(["alice"], ["eng"])),
+            ),
+            (
+                "standups".to_string(),
+                Link::new("https://example.com/").with_visibility(Visibility::Unlisted),
+            ),
+            ("stand".to_string(), Link::new("https://example.com/")),
+        ]);
+        assert_eq!(
+            resolver.resolve("/standpu", &links),
+            not_found("standpu", &["stand"])
+        );
+        let alice = RequestContext::new().with_user(User::new("alice"));
+        assert_eq!(
+            resolver.resolve_with_context("/standpu", &alice, &links),
+            not_found("standpu", &["standup", "stand"])
+        );
+        // Closures can't list their links
+        assert_eq!(
+            resolver.resolve("/standpu", &lookup),
+            not_found("standpu", &[])
+        );
+    }
+
+    #[test]
+    fn it_falls_back_for_unknown_shortlinks() {
+        let fallback = |url: &str, shortlink: &str| {
+            Ok(GolinkResolution::Fallback {
+                url: url.to_string(),
+                shortlink: shortlink.to_string(),
+            })
+        };
+
+        let search = Resolver::builder()
+            .fallback(FallbackPolicy::Search(
+                "https://search.example.com/?q={ shortlink }{{ if path }}+{ path }{{ endif }}"
+                    .to_string(),
+            ))
+            .build();
+        assert_eq!(
+            search.resolve("/Stand-Up/notes", &lookup),
+            fallback("https://search.example.com/?q=standup+notes", "standup")
+        );
+        // Stored links still resolve, and metadata requests still fail
+        assert!(matches!(
+            search.resolve("/abcd", &lookup),
+            Ok(GolinkResolution::RedirectRequest { url, .. }) if url == "efgh"
+        ));
+        assert_eq!(
+            search.resolve("/standup+", &lookup),
+            Err(GolinkError::NotFound {
+                shortlink: "standup".to_string(),
+                suggestions: vec![],
+            })
+        );
+
+        let create = Resolver::builder()
+            .fallback(FallbackPolicy::Create(
+                "https://go.example.com/.create?shortlink={ shortlink }".to_string(),
+            ))
+            .build();
+        assert_eq!(
+            create.resolve("/standup", &lookup),
+            fallback(
+                "https://go.example.com/.create?shortlink=standup",
+                "standup"
+            )
+        );
+
+        let upstream = Resolver::builder()
+            .fallback(FallbackPolicy::Upstream(
+                "https://go.other.example.com/".to_string(),
+            ))
+            .build();
+        assert_eq!(
+            upstream.resolve("/Stand-Up/a%20b?q=1", &lookup),
+            fallback("https://go.other.example.com/Stand-Up/a%20b?q=1", "standup")
+        );
+
+        // Fallback URLs are held to the safety policy too
+        let unsafe_upstream = Resolver::builder()
+            .fallback(FallbackPolicy::Upstream(
+                "javascript:alert(1)//".to_string(),
+            ))
+            .build();
+        assert!(matches!(
+            unsafe_upstream.resolve("/standup", &lookup),
+            Err(GolinkError::UnsafeTarget { .. })
+        ));
+    }
+
+    #[test]
+    fn it_falls_back_with_the_configured_template_engine() {
+        let search = |engine, template: &str| {
+            let resolver = Resolver::builder()
+                .template_engine(engine)
+                .fallback(FallbackPolicy::Search(template.to_string()))
+                .build();
+            match resolver.resolve("/Stand-Up/notes", &lookup) {
+                Ok(GolinkResolution::Fallback { url, .. }) => url,
+                other => panic!("unexpected resolution {other:?}"),
+            }
+        };
+
+        let template = "https://search.example.com/?q={ shortlink }";
+        assert_eq!(
+            search(TemplateEngine::TinyTemplate, template),
+            "https://search.example.com/?q=standup"
+        );
+        // A URL that isn't a template is used as-is, with the request's path appended
+        assert_eq!(
+            search(TemplateEngine::Disabled, template),
+            "https://search.example.com/Stand-Up/notes?q={%20shortlink%20}"
+        );
+        assert_eq!(
+            search(TemplateEngine::TinyTemplate, "https://search.example.com/"),
+            "https://search.example.com/Stand-Up/notes"
+        );
+        #[cfg(feature = "go-template")]
+        {
+            assert_eq!(
+                search(TemplateEngine::GoTemplate, template),
+                "https://search.example.com/Stand-Up/notes?q={%20shortlink%20}"
+            );
+            assert_eq!(
+                search(
+                    TemplateEngine::GoTemplate,
+                    "https://search.example.com/?q={{QueryEscape .Path}}"
+                ),
+                "https://search.example.com/?q=notes"
+            );
+        }
+    }
+
+    #[test]
+    fn it_rejects_unsafe_targets() {
+        let store = |shortlink: &str| match shortlink {
+            "xss" => Some("javascript:alert(document.cookie)"),
+            "crlf" => Some("efgh\r\n\r\n<script>"),
+            "evil" => Some("https://evil.example.com/"),
+            _ => Some("efgh"),
+        };
+        let unsafe_target = |shortlink: &str, reason: &str| {
+            Err(GolinkError::UnsafeTarget {
+                shortlink: shortlink.to_string(),
+                reason: reason.to_string(),
+            })
+        };
+
+        let resolver = Resolver::default();
+        assert_eq!(
+            resolver.resolve("/xss", &store),
+            unsafe_target("xss", "scheme 'javascript' is not allowed")
+        );
+        assert_eq!(
+            resolver.resolve("/crlf", &store),
+            unsafe_target("crlf", "contains control characters")
+        );
+        assert!(resolver.resolve("/evil", &store).is_ok());
+        assert!(resolver.resolve("/abcd/a/b", &store).is_ok());
+
+        let resolver = Resolver::builder()
+            .safety_policy(SafetyPolicy::new().denied_hosts(["evil.example.com"]))
+            .build();
+        assert_eq!(
+            resolver.resolve("/evil", &store),
+            unsafe_target("evil", "host 'evil.example.com' is denied")
+        );
+
+        let resolver = Resolver::builder()
+            .safety_policy(SafetyPolicy::permissive())
+            .build();
+        assert!(resolver.resolve("/xss", &store).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "go-template")]
+    fn it_renders_go_templates() {
+        let resolver = Resolver::builder()
+            .template_engine(TemplateEngine::GoTemplate)
+            .build();
+        let store = |shortlink: &str| match shortlink {
+            "search" => {
+                Some("https://example.com/{{if .Path}}search?q={{QueryEscape .Path}}{{end}}")
+            }
+            "tpl" => Some("{ path }"),
+            _ => Some("https://example.com/docs"),
+        };
+        let url = |input| match resolver.resolve(input, &store) {
+            Ok(GolinkResolution::RedirectRequest { url, .. }) => url,
+            other => panic!("unexpected resolution {other:?}"),
+        };
+
+        assert_eq!(url("/search"), "https://example.com/");
+        assert_eq!(url("/search/a b"), "https://example.com/search?q=a+b");
+        assert_eq!(url("/docs/api"), "https://example.com/docs/api");
+        assert_eq!(url("/tpl/a"), "{ path }/a");
+
+        let context = RequestContext::new().with_user(User::new("foo@example.com"));
+        let store = |_: &str| Some("https://example.com/{{.User}}/{{.Path}}");
+        assert!(matches!(
+            resolver.resolve_with_context("/me/a", &context, &store),
+            Ok(GolinkResolution::RedirectRequest { url, .. }) if url == "https://example.com/foo@example.com/a"
+        ));
+    }
+}
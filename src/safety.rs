@@ -0,0 +1,331 @@
+use url::{ParseError, Url};
+
+const DEFAULT_ALLOWED_SCHEMES: &[&str] = &["http", "https"];
+const DEFAULT_MAX_URL_LENGTH: usize = 8192;
+
+/// Rules an expanded URL must follow before the resolver hands it back for a redirect.
+///
+/// A link's long URL is only as trustworthy as whoever last edited it, and templates
+/// can place request values anywhere in it. The safety policy keeps a careless or
+/// malicious edit from turning the go service into an XSS or open-redirect vector:
+/// targets that break it fail with
+/// [`GolinkError::UnsafeTarget`](crate::GolinkError::UnsafeTarget).
+///
+/// The default policy:
+///
+/// - allows only the `http` and `https` schemes, so `javascript:`, `data:` and `file:`
+///   targets are rejected;
+/// - allows relative targets like `efgh/a/b`, except protocol-relative ones like
+///   `//evil.example.com` that browsers treat as another host;
+/// - rejects control characters, such as the CR/LF of a header injection;
+/// - rejects targets longer than 8192 bytes;
+/// - allows any host.
+///
+/// # Examples
+///
+/// ```
+/// use golink::{GolinkError, Resolver, SafetyPolicy};
+///
+/// let resolver = Resolver::builder()
+///     .safety_policy(
+///         SafetyPolicy::new()
+///             .allowed_schemes(["https"])
+///             .denied_hosts(["evil.example.com"]),
+///     )
+///     .build();
+///
+/// let lookup = |shortlink: &str| match shortlink {
+///     "xss" => Some("javascript:alert(1)"),
+///     _ => Some("https://sub.evil.example.com/"),
+/// };
+/// assert!(matches!(
+///     resolver.resolve("/xss", &lookup),
+///     Err(GolinkError::UnsafeTarget { .. })
+/// ));
+/// assert!(matches!(
+///     resolver.resolve("/phish", &lookup),
+///     Err(GolinkError::UnsafeTarget { .. })
+/// ));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafetyPolicy {
+    allowed_schemes: Option<Vec<String>>,
+    allowed_hosts: Option<Vec<String>>,
+    denied_hosts: Vec<String>,
+    allow_relative: bool,
+    reject_control_characters: bool,
+    max_url_length: Option<usize>,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: Some(
+                DEFAULT_ALLOWED_SCHEMES
+                    .iter()
+                    .map(|scheme| (*scheme).to_string())
+                    .collect(),
+            ),
+            allowed_hosts: None,
+            denied_hosts: Vec::new(),
+            allow_relative: true,
+            reject_control_characters: true,
+            max_url_length: Some(DEFAULT_MAX_URL_LENGTH),
+        }
+    }
+}
+
+impl SafetyPolicy {
+    /// Creates the default policy.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a policy that accepts every target, for deployments that vet links some
+    /// other way.
+    #[must_use]
+    pub fn permissive() -> Self {
+        Self {
+            allowed_schemes: None,
+            allowed_hosts: None,
+            denied_hosts: Vec::new(),
+            allow_relative: true,
+            reject_control_characters: false,
+            max_url_length: None,
+        }
+    }
+
+    /// Sets the URL schemes targets may use, compared case-insensitively. Defaults to
+    /// `http` and `https`.
+    #[must_use]
+    pub fn allowed_schemes<I>(mut self, schemes: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.allowed_schemes = Some(lowercase(schemes));
+        self
+    }
+
+    /// Restricts absolute targets to the given hosts and their subdomains. By default
+    /// every host is allowed.
+    #[must_use]
+    pub fn allowed_hosts<I>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.allowed_hosts = Some(lowercase(hosts));
+        self
+    }
+
+    /// Rejects targets on the given hosts and their subdomains, even if they are
+    /// [allowed](Self::allowed_hosts).
+    #[must_use]
+    pub fn denied_hosts<I>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.denied_hosts = lowercase(hosts);
+        self
+    }
+
+    /// Sets whether targets without a scheme, like `efgh/a/b`, are allowed. Defaults to
+    /// `true`. Protocol-relative targets (`//host/...`) are never allowed while a scheme
+    /// or host list is in effect.
+    #[must_use]
+    pub fn allow_relative(mut self, allow_relative: bool) -> Self {
+        self.allow_relative = allow_relative;
+        self
+    }
+
+    /// Sets whether targets containing control characters, such as CR and LF, are
+    /// rejected. Defaults to `true`.
+    #[must_use]
+    pub fn reject_control_characters(mut self, reject_control_characters: bool) -> Self {
+        self.reject_control_characters = reject_control_characters;
+        self
+    }
+
+    /// Sets the longest target, in bytes, that may be returned. Defaults to 8192.
+    #[must_use]
+    pub fn max_url_length(mut self, max_url_length: usize) -> Self {
+        self.max_url_length = Some(max_url_length);
+        self
+    }
+
+    /// Checks an expanded URL, returning the reason it is unsafe if it is.
+    pub(crate) fn check(&self, url: &str) -> Result<(), String> {
+        if let Some(max) = self.max_url_length
+            && url.len() > max
+        {
+            return Err(format!("longer than {max} bytes"));
+        }
+        if self.reject_control_characters && url.chars().any(char::is_control) {
+            return Err("contains control characters".to_string());
+        }
+
+        let parsed = match Url::parse(url) {
+            Ok(parsed) => parsed,
+            Err(ParseError::RelativeUrlWithoutBase) => return self.check_relative(url),
+            // A path on this host can't lead anywhere unexpected, but anything else that
+            // doesn't parse, like `https://evil.com:99999/`, can't have its host checked
+            Err(_) if is_path(url) => return self.check_relative(url),
+            Err(_) => return Err("not a URL".to_string()),
+        };
+
+        if let Some(schemes) = &self.allowed_schemes
+            && !schemes.iter().any(|scheme| scheme == parsed.scheme())
+        {
+            return Err(format!("scheme '{}' is not allowed", parsed.scheme()));
+        }
+
+        let host = parsed.host_str().unwrap_or_default().to_ascii_lowercase();
+        if self
+            .denied_hosts
+            .iter()
+            .any(|denied| matches_host(&host, denied))
+        {
+            return Err(format!("host '{host}' is denied"));
+        }
+        if let Some(allowed) = &self.allowed_hosts
+            && !allowed.iter().any(|allowed| matches_host(&host, allowed))
+        {
+            return Err(format!("host '{host}' is not allowed"));
+        }
+        Ok(())
+    }
+
+    fn check_relative(&self, url: &str) -> Result<(), String> {
+        if !self.allow_relative {
+            return Err("relative targets are not allowed".to_string());
+        }
+
+        // Browsers read `//host`, `\\host` and `/\host` as a URL on another host
+        let restricted = self.allowed_schemes.is_some()
+            || self.allowed_hosts.is_some()
+            || !self.denied_hosts.is_empty();
+        let leading = url.trim_start().chars().take(2).collect::<String>();
+        if restricted && leading.chars().all(|c| c == '/' || c == '\\') && leading.len() == 2 {
+            return Err("protocol-relative targets are not allowed".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn lowercase<I>(values: I) -> Vec<String>
+where
+    I: IntoIterator,
+    I::Item: Into<String>,
+{
+    values
+        .into_iter()
+        .map(|value| value.into().to_ascii_lowercase())
+        .collect()
+}
+
+/// Returns whether `url` is a path on the current host: it starts with a single `/`,
+/// not `//` or `/\`.
+fn is_path(url: &str) -> bool {
+    url.strip_prefix('/')
+        .is_some_and(|rest| !rest.starts_with(['/', '\\']))
+}
+
+/// Returns whether `host` is `pattern` or one of its subdomains.
+fn matches_host(host: &str, pattern: &str) -> bool {
+    host == pattern
+        || host
+            .strip_suffix(pattern)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn it_allows_web_and_relative_targets_by_default() {
+        let policy = SafetyPolicy::default();
+        for url in [
+            "https://example.com/a?b=c",
+            "HTTP://example.com/",
+            "efgh/a/b/c",
+            "/docs",
+        ] {
+            assert_eq!(policy.check(url), Ok(()), "{url}");
+        }
+    }
+
+    #[test]
+    fn it_rejects_unsafe_targets_by_default() {
+        let policy = SafetyPolicy::default();
+        let cases = [
+            ("javascript:alert(1)", "scheme 'javascript' is not allowed"),
+            ("JavaScript:alert(1)", "scheme 'javascript' is not allowed"),
+            ("data:text/html,<script>", "scheme 'data' is not allowed"),
+            ("file:///etc/passwd", "scheme 'file' is not allowed"),
+            ("efgh\r\nSet-Cookie: a=b", "contains control characters"),
+            (
+                "//evil.example.com",
+                "protocol-relative targets are not allowed",
+            ),
+            (
+                "/\\evil.example.com",
+                "protocol-relative targets are not allowed",
+            ),
+        ];
+        for (url, reason) in cases {
+            assert_eq!(policy.check(url), Err(reason.to_string()), "{url}");
+        }
+        assert_eq!(
+            policy.check(&format!("https://example.com/{}", "a".repeat(8192))),
+            Err("longer than 8192 bytes".to_string())
+        );
+    }
+
+    #[test]
+    fn it_checks_hosts() {
+        let policy = SafetyPolicy::new()
+            .allowed_hosts(["Example.com"])
+            .denied_hosts(["admin.example.com"]);
+        assert_eq!(policy.check("https://example.com/"), Ok(()));
+        assert_eq!(policy.check("https://docs.example.com/"), Ok(()));
+        assert_eq!(
+            policy.check("https://notexample.com/"),
+            Err("host 'notexample.com' is not allowed".to_string())
+        );
+        assert_eq!(
+            policy.check("https://a.admin.example.com/"),
+            Err("host 'a.admin.example.com' is denied".to_string())
+        );
+        // Targets that don't parse can't have their host checked
+        for url in [
+            "https://evil.com:99999/",
+            "https://ev il.com/",
+            "http://[::1",
+            "https://",
+        ] {
+            assert_eq!(policy.check(url), Err("not a URL".to_string()), "{url}");
+        }
+    }
+
+    #[test]
+    fn it_can_be_relaxed() {
+        let policy = SafetyPolicy::permissive();
+        assert_eq!(policy.check("javascript:alert(1)"), Ok(()));
+        assert_eq!(policy.check("a\nb"), Ok(()));
+        assert_eq!(policy.check("//evil.example.com"), Ok(()));
+
+        let policy = SafetyPolicy::new()
+            .allowed_schemes(["https", "mailto"])
+            .allow_relative(false);
+        assert_eq!(policy.check("mailto:team@example.com"), Ok(()));
+        assert_eq!(
+            policy.check("efgh"),
+            Err("relative targets are not allowed".to_string())
+        );
+    }
+}
@@ -0,0 +1,473 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::error::Error as StdError;
+use std::future::{self, Future};
+use std::hash::BuildHasher;
+
+use crate::{Candidate, Link, RequestContext};
+
+/// A synchronous source of [`Link`]s, keyed by normalized shortlink.
+///
+/// Implemented for closures that return `Option<T>` or `Result<Option<T>, E>` where `T`
+/// converts into a [`Link`] (see [`IntoLookupResult`]), and for `HashMap<String, V>` and
+/// `BTreeMap<String, V>` holding either `Link`s or long URL `String`s, so an in-memory
+/// map can be swapped for a database-backed store without changing how the resolver is
+/// called.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+/// use std::convert::Infallible;
+/// use golink::{GolinkResolution, Link, LinkStore, Resolver};
+///
+/// struct Links(HashMap<String, Link>);
+///
+/// impl LinkStore for Links {
+///     type Error = Infallible;
+///
+///     fn lookup(&self, shortlink: &str) -> Result<Option<Link>, Infallible> {
+///         Ok(self.0.get(shortlink).cloned())
+///     }
+/// }
+///
+/// let home = Link::new("https://example.com/").with_owner("jameslittle230");
+/// let links = Links(HashMap::from([("home".to_string(), home)]));
+/// let resolved = Resolver::default().resolve("/home", &links);
+/// assert!(matches!(resolved, Ok(GolinkResolution::RedirectRequest { .. })));
+/// ```
+pub trait LinkStore {
+    /// The error returned when the store can't be read, reported as
+    /// [`GolinkError::LookupFailed`](crate::GolinkError::LookupFailed).
+    type Error: StdError + Send + Sync + 'static;
+
+    /// Returns the link stored for `shortlink`, or `None` if there isn't one.
+    ///
+    /// `shortlink` has already been normalized by the resolver.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store couldn't determine whether the link exists.
+    fn lookup(&self, shortlink: &str) -> Result<Option<Link>, Self::Error>;
+
+    /// Returns the link stored for `shortlink` as seen by the request described by
+    /// `context`, e.g. a user's own version of a link.
+    ///
+    /// The resolver always calls this method; the default implementation ignores the
+    /// context and calls [`lookup`](Self::lookup).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store couldn't determine whether the link exists.
+    fn lookup_with_context(
+        &self,
+        shortlink: &str,
+        context: &RequestContext,
+    ) -> Result<Option<Link>, Self::Error> {
+        let _ = context;
+        self.lookup(shortlink)
+    }
+
+    /// Lists the shortlinks the resolver may suggest when a request isn't found.
+    ///
+    /// Only called by resolvers with [suggestions](crate::ResolverBuilder::suggestions)
+    /// enabled, after a lookup fails. The default implementation lists nothing, so no
+    /// suggestions are made. Attach each candidate's link with
+    /// [`Candidate::with_link`] so links the requester may not resolve, or that are
+    /// unlisted, are left out; candidates without one are suggested to anyone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store couldn't be read.
+    fn shortlinks(&self) -> Result<Vec<Candidate>, Self::Error> {
+        Ok(Vec::new())
+    }
+}
+
+/// An asynchronous source of [`Link`]s, keyed by normalized shortlink.
+///
+/// Implemented for closures returning a future whose output is `Option<T>` or
+/// `Result<Option<T>, E>` where `T` converts into a [`Link`] (see
+/// [`IntoLookupResult`]), and for `HashMap<String, V>` and `BTreeMap<String, V>`.
+///
+/// # Examples
+///
+/// ```
+/// use golink::{AsyncLinkStore, GolinkError, Link, Resolver};
+///
+/// #[derive(Debug)]
+/// struct DatabaseError;
+///
+/// impl std::fmt::Display for DatabaseError {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         write!(f, "database unavailable")
+///     }
+/// }
+///
+/// impl std::error::Error for DatabaseError {}
+///
+/// struct Database;
+///
+/// impl AsyncLinkStore for Database {
+///     type Error = DatabaseError;
+///
+///     async fn lookup(&self, shortlink: &str) -> Result<Option<Link>, DatabaseError> {
+///         // Query your database here
+///         Err(DatabaseError)
+///     }
+/// }
+///
+/// # async fn example() {
+/// let resolved = Resolver::default().resolve_async("/home", &Database).await;
+/// assert!(matches!(resolved, Err(GolinkError::LookupFailed(_))));
+/// # }
+/// ```
+pub trait AsyncLinkStore {
+    /// The error returned when the store can't be read, reported as
+    /// [`GolinkError::LookupFailed`](crate::GolinkError::LookupFailed).
+    type Error: StdError + Send + Sync + 'static;
+
+    /// Returns the link stored for `shortlink`, or `None` if there isn't one.
+    ///
+    /// `shortlink` has already been normalized by the resolver.
+    fn lookup(&self, shortlink: &str) -> impl Future<Output = Result<Option<Link>, Self::Error>>;
+
+    /// Returns the link stored for `shortlink` as seen by the request described by
+    /// `context`, e.g. a user's own version of a link.
+    ///
+    /// The resolver always calls this method; the default implementation ignores the
+    /// context and calls [`lookup`](Self::lookup).
+    fn lookup_with_context(
+        &self,
+        shortlink: &str,
+        context: &RequestContext,
+    ) -> impl Future<Output = Result<Option<Link>, Self::Error>> {
+        let _ = context;
+        self.lookup(shortlink)
+    }
+
+    /// Lists the shortlinks the resolver may suggest when a request isn't found.
+    ///
+    /// See [`LinkStore::shortlinks`].
+    fn shortlinks(&self) -> impl Future<Output = Result<Vec<Candidate>, Self::Error>> {
+        future::ready(Ok(Vec::new()))
+    }
+}
+
+/// Values that a lookup closure may return.
+///
+/// Infallible lookups return `Option<T>`; lookups that can fail (e.g. a database
+/// query) return `Result<Option<T>, E>` so that failures surface as
+/// [`GolinkError::LookupFailed`](crate::GolinkError::LookupFailed) instead of
+/// [`GolinkError::NotFound`](crate::GolinkError::NotFound). `T` is either a full
+/// [`Link`] or anything that converts into one, like a long URL `String`.
+pub trait IntoLookupResult {
+    /// The error type of a failed lookup.
+    type Error: StdError + Send + Sync + 'static;
+
+    /// Converts the closure's return value into a lookup result.
+    ///
+    /// # Errors
+    ///
+    /// Returns the lookup's own error, if it failed.
+    fn into_lookup_result(self) -> Result<Option<Link>, Self::Error>;
+}
+
+impl<T: Into<Link>> IntoLookupResult for Option<T> {
+    type Error = Infallible;
+
+    fn into_lookup_result(self) -> Result<Option<Link>, Infallible> {
+        Ok(self.map(Into::into))
+    }
+}
+
+impl<T, E> IntoLookupResult for Result<Option<T>, E>
+where
+    T: Into<Link>,
+    E: StdError + Send + Sync + 'static,
+{
+    type Error = E;
+
+    fn into_lookup_result(self) -> Result<Option<Link>, E> {
+        self.map(|link| link.map(Into::into))
+    }
+}
+
+impl<F, R> LinkStore for F
+where
+    F: Fn(&str) -> R,
+    R: IntoLookupResult,
+{
+    type Error = R::Error;
+
+    fn lookup(&self, shortlink: &str) -> Result<Option<Link>, R::Error> {
+        self(shortlink).into_lookup_result()
+    }
+}
+
+impl<F, Fut> AsyncLinkStore for F
+where
+    F: Fn(&str) -> Fut,
+    Fut: Future,
+    Fut::Output: IntoLookupResult,
+{
+    type Error = <Fut::Output as IntoLookupResult>::Error;
+
+    fn lookup(&self, shortlink: &str) -> impl Future<Output = Result<Option<Link>, Self::Error>> {
+        let lookup = self(shortlink);
+        async move { lookup.await.into_lookup_result() }
+    }
+}
+
+impl<V, H> LinkStore for HashMap<String, V, H>
+where
+    V: Clone + Into<Link>,
+    H: BuildHasher,
+{
+    type Error = Infallible;
+
+    fn lookup(&self, shortlink: &str) -> Result<Option<Link>, Infallible> {
+        Ok(self.get(shortlink).cloned().map(Into::into))
+    }
+
+    fn shortlinks(&self) -> Result<Vec<Candidate>, Infallible> {
+        Ok(self
+            .iter()
+            .map(|(shortlink, link)| {
+                Candidate::new(shortlink.as_str()).with_link(link.clone().into())
+            })
+            .collect())
+    }
+}
+
+impl<V, H> AsyncLinkStore for HashMap<String, V, H>
+where
+    V: Clone + Into<Link>,
+    H: BuildHasher,
+{
+    type Error = Infallible;
+
+    fn lookup(&self, shortlink: &str) -> impl Future<Output = Result<Option<Link>, Infallible>> {
+        future::ready(LinkStore::lookup(self, shortlink))
+    }
+
+    fn shortlinks(&self) -> impl Future<Output = Result<Vec<Candidate>, Infallible>> {
+        future::ready(LinkStore::shortlinks(self))
+    }
+}
+
+impl<V> LinkStore for BTreeMap<String, V>
+where
+    V: Clone + Into<Link>,
+{
+    type Error = Infallible;
+
+    fn lookup(&self, shortlink: &str) -> Result<Option<Link>, Infallible> {
+        Ok(self.get(shortlink).cloned().map(Into::into))
+    }
+
+    fn shortlinks(&self) -> Result<Vec<Candidate>, Infallible> {
+        Ok(self
+            .iter()
+            .map(|(shortlink, link)| {
+                Candidate::new(shortlink.as_str()).with_link(link.clone().into())
+            })
+            .collect())
+    }
+}
+
+impl<V> AsyncLinkStore for BTreeMap<String, V>
+where
+    V: Clone + Into<Link>,
+{
+    type Error = Infallible;
+
+    fn lookup(&self, shortlink: &str) -> impl Future<Output = Result<Option<Link>, Infallible>> {
+        future::ready(LinkStore::lookup(self, shortlink))
+    }
+
+    fn shortlinks(&self) -> impl Future<Output = Result<Vec<Candidate>, Infallible>> {
+        future::ready(LinkStore::shortlinks(self))
+    }
+}
+
+/// Adapts a [`LinkStore`] so the shared resolution pipeline can await it.
+pub(crate) struct Blocking<'a, S: ?Sized>(pub(crate) &'a S);
+
+impl<S: LinkStore + ?Sized> AsyncLinkStore for Blocking<'_, S> {
+    type Error = S::Error;
+
+    fn lookup(&self, shortlink: &str) -> impl Future<Output = Result<Option<Link>, Self::Error>> {
+        future::ready(self.0.lookup(shortlink))
+    }
+
+    fn lookup_with_context(
+        &self,
+        shortlink: &str,
+        context: &RequestContext,
+    ) -> impl Future<Output = Result<Option<Link>, Self::Error>> {
+        future::ready(self.0.lookup_with_context(shortlink, context))
+    }
+
+    fn shortlinks(&self) -> impl Future<Output = Result<Vec<Candidate>, Self::Error>> {
+        future::ready(self.0.shortlinks())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GolinkError, GolinkResolution, LinkLayer, LookupError, Resolver};
+    use pretty_assertions::assert_eq;
+    use std::fmt;
+
+    fn links() -> Vec<(String, String)> {
+        vec![
+            ("test".to_string(), "http://example.com/".to_string()),
+            ("abcd".to_string(), "efgh".to_string()),
+        ]
+    }
+
+    #[derive(Debug)]
+    struct Unavailable;
+
+    impl fmt::Display for Unavailable {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "store unavailable")
+        }
+    }
+
+    impl StdError for Unavailable {}
+
+    #[test]
+    fn it_resolves_from_a_hash_map() {
+        let store: HashMap<String, String> = links().into_iter().collect();
+        assert_eq!(
+            Resolver::default().resolve("/test/a", &store),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "http://example.com/a".to_string(),
+                shortlink: "test".to_string(),
+                link: Link::new("http://example.com/"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+        assert_eq!(
+            Resolver::default().resolve("/missing", &store),
+            Err(GolinkError::NotFound {
+                shortlink: "missing".to_string(),
+                suggestions: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn it_resolves_from_a_btree_map() {
+        let store: BTreeMap<String, String> = links().into_iter().collect();
+        assert_eq!(
+            Resolver::default().resolve("/ab-cd", &store),
+            Ok(GolinkResolution::RedirectRequest {
+                url: "efgh".to_string(),
+                shortlink: "abcd".to_string(),
+                link: Link::new("efgh"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn async_it_resolves_from_a_hash_map() {
+        let store: HashMap<String, String> = links().into_iter().collect();
+        assert_eq!(
+            Resolver::default().resolve_async("/TEST", &store).await,
+            Ok(GolinkResolution::RedirectRequest {
+                url: "http://example.com/".to_string(),
+                shortlink: "test".to_string(),
+                link: Link::new("http://example.com/"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn async_it_resolves_from_a_closure() {
+        let store = |shortlink: &str| {
+            let found = shortlink == "test";
+            async move { found.then(|| "http://example.com/".to_string()) }
+        };
+        assert_eq!(
+            Resolver::default().resolve_async("/test", &store).await,
+            Ok(GolinkResolution::RedirectRequest {
+                url: "http://example.com/".to_string(),
+                shortlink: "test".to_string(),
+                link: Link::new("http://example.com/"),
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+
+    #[test]
+    fn it_reports_failed_lookups_separately_from_missing_links() {
+        let failing = |_: &str| -> Result<Option<String>, Unavailable> { Err(Unavailable) };
+        let computed = Resolver::default().resolve("/test", &failing);
+        let Err(GolinkError::LookupFailed(error)) = computed else {
+            panic!("Expected LookupFailed, got {computed:?}");
+        };
+        assert!(error.downcast_ref::<Unavailable>().is_some());
+        assert_eq!(error.get_ref().to_string(), "store unavailable");
+        assert_ne!(error, LookupError::new(Unavailable));
+        assert_eq!(error, error.clone());
+
+        // Walking the source chain reaches the store's error, and prints it once
+        let computed = Resolver::default().resolve("/test", &failing).unwrap_err();
+        let mut chain = vec![computed.to_string()];
+        let mut source = computed.source();
+        while let Some(error) = source {
+            chain.push(error.to_string());
+            source = error.source();
+        }
+        assert_eq!(chain, vec!["Lookup failed", "store unavailable"]);
+
+        let missing = |_: &str| -> Result<Option<String>, Unavailable> { Ok(None) };
+        assert_eq!(
+            Resolver::default().resolve("/test", &missing),
+            Err(GolinkError::NotFound {
+                shortlink: "test".to_string(),
+                suggestions: Vec::new(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn async_it_reports_failed_lookups() {
+        let store = |_: &str| async { Err::<Option<String>, _>(Unavailable) };
+        let computed = Resolver::default().resolve_async("/test", &store).await;
+        assert!(matches!(computed, Err(GolinkError::LookupFailed(_))));
+    }
+
+    #[test]
+    fn it_passes_link_metadata_through_to_the_resolution() {
+        let docs = Link::new("https://docs.example.com")
+            .with_owner("docs-team")
+            .with_tags(["docs"])
+            .with_redirect_kind(crate::RedirectKind::Permanent);
+        let store = BTreeMap::from([("docs".to_string(), docs.clone())]);
+
+        let redirect = Resolver::default().resolve("/docs/api", &store).unwrap();
+        assert_eq!(redirect.link(), Some(&docs));
+        assert_eq!(redirect.link().unwrap().redirect_kind.status_code(), 301);
+
+        assert_eq!(
+            Resolver::default().resolve("/docs+", &store),
+            Ok(GolinkResolution::MetadataRequest {
+                shortlink: "docs".to_string(),
+                link: docs,
+                layer: LinkLayer::Global,
+                trimmed: None,
+            })
+        );
+    }
+}
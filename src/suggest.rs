@@ -0,0 +1,206 @@
+use std::cmp::Reverse;
+
+use itertools::Itertools;
+
+use crate::{Link, User, Visibility};
+
+/// A stored shortlink that may be suggested when a request isn't found.
+///
+/// Stores list their shortlinks through
+/// [`LinkStore::shortlinks`](crate::LinkStore::shortlinks), weighting popular ones so
+/// they are suggested ahead of equally close but rarely used links. Candidates that
+/// carry their [`Link`] are only suggested to requesters who may resolve it, and never
+/// if it's [unlisted](Visibility::Unlisted).
+///
+/// # Examples
+///
+/// ```
+/// use golink::Candidate;
+///
+/// let candidate = Candidate::new("standup").with_popularity(1200);
+/// assert_eq!(candidate.shortlink, "standup");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Candidate {
+    /// The normalized shortlink, as passed to lookups.
+    pub shortlink: String,
+
+    /// How popular the link is, e.g. its visit count. Only compared with other
+    /// candidates, so any scale works.
+    pub popularity: u64,
+
+    /// The stored link, if the store has it at hand, so that it's only suggested to
+    /// requesters who may resolve it.
+    pub link: Option<Link>,
+}
+
+impl Candidate {
+    /// Creates a candidate with no popularity.
+    #[must_use]
+    pub fn new(shortlink: impl Into<String>) -> Self {
+        Self {
+            shortlink: shortlink.into(),
+            popularity: 0,
+            link: None,
+        }
+    }
+
+    /// Sets how popular the link is.
+    #[must_use]
+    pub fn with_popularity(mut self, popularity: u64) -> Self {
+        self.popularity = popularity;
+        self
+    }
+
+    /// Sets the stored link, so the candidate is left out of suggestions for requesters
+    /// who may not resolve it, and when it's unlisted.
+    #[must_use]
+    pub fn with_link(mut self, link: Link) -> Self {
+        self.link = Some(link);
+        self
+    }
+
+    /// Returns whether the candidate may be suggested to `user` (or to an anonymous
+    /// request, for `None`).
+    fn is_suggestable_to(&self, user: Option<&User>) -> bool {
+        self.link
+            .as_ref()
+            .is_none_or(|link| link.visibility == Visibility::Public && link.is_accessible_by(user))
+    }
+}
+
+impl From<String> for Candidate {
+    fn from(shortlink: String) -> Self {
+        Self::new(shortlink)
+    }
+}
+
+impl From<&str> for Candidate {
+    fn from(shortlink: &str) -> Self {
+        Self::new(shortlink)
+    }
+}
+
+/// The shortest request that is suggested the shortlinks it's a prefix of.
+const MIN_PREFIX_LENGTH: usize = 3;
+
+/// Picks up to `limit` candidates that look like typos of `shortlink`.
+///
+/// A candidate is close if it's within a few edits of `shortlink`, counting a swap of
+/// adjacent characters as one edit, or if either one is a prefix of the other. Closer
+/// candidates come first, then more popular ones. Personal and group links are never
+/// suggested, so one user's links don't show up in another's suggestions, and neither
+/// are links `user` may not resolve or that are unlisted.
+pub(crate) fn suggest(
+    shortlink: &str,
+    candidates: Vec<Candidate>,
+    limit: usize,
+    user: Option<&User>,
+) -> Vec<String> {
+    let length = shortlink.chars().count();
+    let max_distance = match length {
+        0..=4 => 1,
+        5..=8 => 2,
+        _ => 3,
+    };
+
+    candidates
+        .into_iter()
+        .filter(|candidate| {
+            candidate.shortlink != shortlink
+                && !candidate.shortlink.starts_with(['~', '@'])
+                && candidate.is_suggestable_to(user)
+        })
+        .filter_map(|candidate| {
+            let distance = strsim::damerau_levenshtein(shortlink, &candidate.shortlink);
+            let is_prefix = length >= MIN_PREFIX_LENGTH
+                && (candidate.shortlink.starts_with(shortlink)
+                    || shortlink.starts_with(&candidate.shortlink));
+            // Prefix matches count as the furthest allowed typo, however long they are
+            let distance = if is_prefix {
+                distance.min(max_distance)
+            } else {
+                distance
+            };
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .sorted_by_key(|(distance, candidate)| {
+            (
+                *distance,
+                Reverse(candidate.popularity),
+                candidate.shortlink.clone(),
+            )
+        })
+        .dedup_by(|(_, a), (_, b)| a.shortlink == b.shortlink)
+        .take(limit)
+        .map(|(_, candidate)| candidate.shortlink)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Access;
+    use pretty_assertions::assert_eq;
+
+    fn candidates() -> Vec<Candidate> {
+        vec![
+            Candidate::new("standup"),
+            Candidate::new("standups").with_popularity(10),
+            Candidate::new("stand"),
+            Candidate::new("docs").with_popularity(100),
+            Candidate::new("dogs"),
+            Candidate::new("~alice/standup"),
+            Candidate::new("unrelated"),
+        ]
+    }
+
+    #[test]
+    fn it_suggests_typos_and_prefixes() {
+        assert_eq!(
+            suggest("standpu", candidates(), 5, None),
+            vec!["standup", "standups", "stand"]
+        );
+        assert_eq!(
+            suggest("stan", candidates(), 5, None),
+            vec!["standups", "stand", "standup"]
+        );
+        assert_eq!(suggest("xyz", candidates(), 5, None), Vec::<String>::new());
+    }
+
+    #[test]
+    fn it_prefers_popular_links() {
+        assert_eq!(suggest("dos", candidates(), 5, None), vec!["docs", "dogs"]);
+        assert_eq!(suggest("dos", candidates(), 1, None), vec!["docs"]);
+    }
+
+    #[test]
+    fn it_leaves_out_links_the_requester_may_not_see() {
+        let candidates = || {
+            vec![
+                Candidate::new("docs").with_link(Link::new("https://example.com/")),
+                Candidate::new("dogs").with_link(
+                    Link::new("https://example.com/").with_access(Access::Authenticated),
+                ),
+                Candidate::new("dots").with_link(
+                    Link::new("https://example.com/").with_visibility(Visibility::Unlisted),
+                ),
+                Candidate::new("doms").with_link(
+                    Link::new("https://example.com/")
+                        .with_access(Access::restricted(["bob"], ["hr"])),
+                ),
+            ]
+        };
+
+        assert_eq!(suggest("dos", candidates(), 5, None), vec!["docs"]);
+        assert_eq!(
+            suggest("dos", candidates(), 5, Some(&User::new("alice"))),
+            vec!["docs", "dogs"]
+        );
+        assert_eq!(
+            suggest("dos", candidates(), 5, Some(&User::new("bob"))),
+            vec!["docs", "dogs", "doms"]
+        );
+    }
+}